@@ -1,43 +1,57 @@
-/*
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use pixpox_app::{App};
-use winit::dpi::LogicalPosition;
-
-fn fibonacci(n: u64) -> u64 {
-    match n {
-        0 => 1,
-        1 => 1,
-        n => fibonacci(n - 1) + fibonacci(n - 2),
-    }
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use pixpox_ecs::{Component, World};
+
+// Same grid as the ecs example
+const WIDTH: isize = 521;
+const HEIGHT: isize = 310;
+
+#[derive(Clone, Component)]
+#[allow(dead_code)] // only spawned, never read
+struct Cell {
+    pos: (isize, isize),
+    alive: bool,
 }
 
-fn ecs(c: &mut Criterion) {
-    // TODO: read config from file
-    let config = AppConfig {
-        WINDOW_TITLE: "Conway",
-        WINDOW_HEIGHT: 250,
-        WINDOW_WIDTH: 500,
-        WINDOW_FULLSCREEN: false,
-        DEBUG: true,
-    };
-
-    let mut app = App::new(config);
-
-    let entity = app.world.spawn();
-
-    let pos = LogicalPosition::new(0, 0);
-    let alive = true;
-
-    let cell_component = Cell::new(entity.id, pos, alive);
-
-    app.world.add_component_to_entity(entity, cell_component);
+fn cells() -> impl Iterator<Item = Cell> {
+    (0..HEIGHT).flat_map(|y| {
+        (0..WIDTH).map(move |x| Cell {
+            pos: (x, y),
+            alive: (x * 7 + y * 13) % 10 == 0,
+        })
+    })
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("fib 20", |b| b.iter(|| fibonacci(black_box(20))));
+fn spawn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spawn 521x310 grid");
+    group.sample_size(10);
+
+    group.bench_function("spawn_batch", |b| {
+        b.iter_batched(
+            World::new,
+            |mut world| {
+                black_box(world.spawn_batch(cells().map(|cell| (cell,))));
+                world
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("spawn + add_component_to_entity", |b| {
+        b.iter_batched(
+            World::new,
+            |mut world| {
+                for cell in cells() {
+                    let entity = world.spawn();
+                    world.add_component_to_entity(entity, cell);
+                }
+                world
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, spawn);
 criterion_main!(benches);
-
-*/
\ No newline at end of file
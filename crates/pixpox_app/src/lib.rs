@@ -1,4 +1,7 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
 
 use serde_derive::{Deserialize, Serialize};
 
@@ -15,19 +18,86 @@ use winit::{
 use pixpox_common::Camera;
 
 use pixpox_ecs::{
-    component::Texture as RenderTexture, GlobalPixelMap as GlobalPixelMapTrait, World,
+    change_detection::Ticks, component::Texture as RenderTexture, timestep,
+    GlobalPixelMap as GlobalPixelMapTrait, Stats, Tracer, World,
 };
 use winit_input_helper::WinitInputHelper;
 
 use log::{error, info};
 
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub window_title: String,
     pub window_height: u32,
     pub window_width: u32,
     pub window_scale: f32,
     pub window_fullscreen: bool,
+    /// Simulation ticks per second, independent of the frame rate.
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: f64,
+    /// Most ticks a single frame may run to catch up after a slow frame.
+    #[serde(default = "default_max_ticks_per_frame")]
+    pub max_ticks_per_frame: u32,
+    /// Multiplies the simulation speed, e.g. 0.5 for slow motion.
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64,
+    /// Where trace captures are written, as Chrome `trace_event` JSON. F9 starts and stops a
+    /// capture.
+    #[serde(default = "default_trace_path")]
+    pub trace_path: String,
+    /// Starts a trace capture as soon as the app starts.
+    #[serde(default)]
+    pub trace_on_start: bool,
+    /// Stops and writes a trace capture after this many seconds, or only on F9 or exit if 0.
+    #[serde(default)]
+    pub trace_seconds: f64,
+    /// Where recorded metrics are written, as JSON if the path ends in `.json` and as CSV
+    /// otherwise. F10 starts and stops a recording, which is also written on exit.
+    #[serde(default = "default_metrics_path")]
+    pub metrics_path: String,
+    /// Starts recording metrics as soon as the app starts.
+    #[serde(default)]
+    pub record_on_start: bool,
+}
+
+fn default_tick_rate() -> f64 {
+    timestep::DEFAULT_TICK_RATE
+}
+
+fn default_max_ticks_per_frame() -> u32 {
+    timestep::DEFAULT_MAX_TICKS_PER_FRAME
+}
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+fn default_trace_path() -> String {
+    "trace.json".to_owned()
+}
+
+fn default_metrics_path() -> String {
+    "metrics.csv".to_owned()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_title: String::default(),
+            window_height: 0,
+            window_width: 0,
+            window_scale: 0.0,
+            window_fullscreen: false,
+            tick_rate: default_tick_rate(),
+            max_ticks_per_frame: default_max_ticks_per_frame(),
+            time_scale: default_time_scale(),
+            trace_path: default_trace_path(),
+            trace_on_start: false,
+            trace_seconds: 0.0,
+            metrics_path: default_metrics_path(),
+            record_on_start: false,
+        }
+    }
 }
 
 pub struct App<'a> {
@@ -38,6 +108,7 @@ pub struct App<'a> {
     window: Window,
     input: WinitInputHelper,
     config: Config,
+    last_render_tick: u32, // change tick of the world when the pixel map was last rendered
 }
 
 impl<'a> App<'a> {
@@ -46,7 +117,25 @@ impl<'a> App<'a> {
         // Initialize WGPU logging
         env_logger::init();
 
-        let world = World::new();
+        let mut world = World::new();
+
+        let clock = world.clock_mut();
+        clock.set_tick_rate(config.tick_rate);
+        clock.set_max_ticks_per_frame(config.max_ticks_per_frame);
+        clock.set_time_scale(config.time_scale);
+
+        if config.trace_on_start {
+            world.tracer.start();
+        }
+
+        if config.record_on_start {
+            world
+                .stats
+                .get_mut()
+                .expect("Could not lock stats")
+                .recorder_mut()
+                .start();
+        }
 
         // Define the event loop
         let event_loop = EventLoop::new();
@@ -94,45 +183,71 @@ impl<'a> App<'a> {
             event_loop,
             window,
             config,
+            last_render_tick: 0,
         }
     }
 
     pub async fn run<T: 'static + GlobalPixelMapTrait>(&mut self) {
+        let tracer = Arc::clone(&self.world.tracer);
+
         self.event_loop.run_return(|event, _target, control_flow| {
             // debug!("Event loop");
             let mut camera: Camera;
 
             // The one and only event that winit_input_helper doesn't have for us...
             if let Event::RedrawRequested(_) = event {
-                // Run components
-                self.world.run::<T>();
+                let _span = tracer.span("app", "frame");
+
+                // Stop a timed trace capture once it has run long enough
+                if tracer.is_recording()
+                    && self.config.trace_seconds > 0.0
+                    && tracer.elapsed().as_secs_f64() >= self.config.trace_seconds
+                {
+                    save_trace(&tracer, &self.config.trace_path);
+                }
+
+                // Run however many fixed ticks this frame is worth
+                self.world.advance::<T>();
 
                 // Get screen frame to render to
                 let pixels = self.pixels.get_frame_mut();
 
                 // Lock storage
-                let mut storage = self.world.storage.write().unwrap();
+                let storage = self.world.storage.read().unwrap();
 
                 // Fetch Global Pixelmap
                 let pixelmap = storage
-                    .query_global_pixel_map::<GlobalPixelMap>()
+                    .global_pixel_map::<GlobalPixelMap>()
                     .expect("Could not query Pixel Map");
 
                 camera = pixelmap.get_camera();
 
-                // Render Global Pixelmap to frame
-                pixelmap.render(pixels);
+                // Render Global Pixelmap to frame, unless it is unchanged since the last frame
+                let ticks = Ticks::new(self.last_render_tick, self.world.change_tick());
+                let changed = storage
+                    .global_pixel_map_ticks()
+                    .map_or(true, |pixelmap_ticks| pixelmap_ticks.is_changed(ticks));
+
+                if changed {
+                    let _span = tracer.span("app", "GlobalPixelMap::render");
+                    pixelmap.render(pixels);
+                }
+
+                self.last_render_tick = self.world.change_tick();
 
                 // Prepare Dear ImGui
+                let gui_span = tracer.span("app", "ImGui prepare");
                 self.gui
                     .prepare(&self.window)
                     .expect("gui.prepare() failed");
+                drop(gui_span);
 
                 let _render_result = self.pixels.render_with(|encoder, render_target, context| {
                     // Render the world texture
                     context.scaling_renderer.render(encoder, render_target);
 
                     // Render Dear ImGui
+                    let _span = tracer.span("app", "ImGui render");
                     self.gui.render(
                         &self.window,
                         encoder,
@@ -154,16 +269,46 @@ impl<'a> App<'a> {
             // For everything else, for let winit_input_helper collect events to build its state.
             // It returns `true` when it is time to update our game state and request a redraw.
             if self.input.update(&event) {
+                let _span = tracer.span("app", "handle input");
+
                 // Close events
                 if self.input.key_pressed(VirtualKeyCode::Escape) || self.input.quit() {
+                    if tracer.is_recording() {
+                        save_trace(&tracer, &self.config.trace_path);
+                    }
+
+                    if self.world.stats.read().unwrap().recorder().is_recording() {
+                        save_metrics(&self.world.stats, &self.config.metrics_path);
+                    }
+
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
 
-                let mut storage = self.world.storage.write().unwrap();
+                // Start or stop a trace capture
+                if self.input.key_pressed(VirtualKeyCode::F9) {
+                    if tracer.is_recording() {
+                        save_trace(&tracer, &self.config.trace_path);
+                    } else {
+                        tracer.start();
+                        info!("Started trace capture");
+                    }
+                }
+
+                // Start or stop recording metrics
+                if self.input.key_pressed(VirtualKeyCode::F10) {
+                    if self.world.stats.read().unwrap().recorder().is_recording() {
+                        save_metrics(&self.world.stats, &self.config.metrics_path);
+                    } else {
+                        self.world.stats.write().unwrap().recorder_mut().start();
+                        info!("Started recording metrics");
+                    }
+                }
+
+                let storage = self.world.storage.read().unwrap();
 
                 let pixelmap = storage
-                    .query_global_pixel_map::<GlobalPixelMap>()
+                    .global_pixel_map::<GlobalPixelMap>()
                     .expect("Could not query Pixel Map");
 
                 camera = pixelmap.get_camera();
@@ -225,3 +370,25 @@ impl<'a> App<'a> {
         });
     }
 }
+
+// Stops the running trace capture and writes it to `path`.
+fn save_trace(tracer: &Tracer, path: &str) {
+    tracer.stop();
+
+    match tracer.save(path) {
+        Ok(()) => info!("Saved trace of {} events to {}", tracer.len(), path),
+        Err(err) => error!("Could not save trace to {path}: {err}"),
+    }
+}
+
+// Stops recording metrics and writes the recording to `path`.
+fn save_metrics(stats: &RwLock<Stats>, path: &str) {
+    let mut stats = stats.write().expect("Could not write lock stats");
+    let recorder = stats.recorder_mut();
+    recorder.stop();
+
+    match recorder.save(path) {
+        Ok(()) => info!("Saved {} ticks of metrics to {}", recorder.len(), path),
+        Err(err) => error!("Could not save metrics to {path}: {err}"),
+    }
+}
@@ -0,0 +1,818 @@
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    sync::RwLock,
+};
+
+use log::{debug, warn};
+use pixpox_utils::{InputHandler, Stats};
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{
+    change_detection::{ComponentTicks, Mut},
+    entity::Entity,
+    hooks::Hooks,
+    Commands, Component, Run, Storage, Update,
+};
+
+/// A type-erased, densely packed column holding one component type, backed by a `Vec<T>`.
+pub trait Column: Send + Sync {
+    fn as_any(&self) -> &(dyn Any + Send + Sync);
+    fn as_any_mut(&mut self) -> &mut (dyn Any + Send + Sync);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn reserve(&mut self, additional: usize);
+    /// Removes the row by swapping in the last one and drops the removed value.
+    fn swap_remove_drop(&mut self, row: usize);
+    /// Removes the row by swapping in the last one and pushes the removed value onto `other`,
+    /// which must be a column of the same type.
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn Column);
+}
+
+impl<T: 'static + Send + Sync> Column for Vec<T> {
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self as &(dyn Any + Send + Sync)
+    }
+
+    fn as_any_mut(&mut self) -> &mut (dyn Any + Send + Sync) {
+        self as &mut (dyn Any + Send + Sync)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional)
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) {
+        self.swap_remove(row);
+    }
+
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn Column) {
+        let value = self.swap_remove(row);
+
+        other
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Column::swap_remove_into() called with a column of another type")
+            .push(value);
+    }
+}
+
+type RunFn = fn(&mut dyn Column, &mut [ComponentTicks], u32, &RwLock<Storage>, &Commands);
+type UpdateFn = fn(
+    &mut dyn Column,
+    &mut [ComponentTicks],
+    u32,
+    &RwLock<Storage>,
+    &InputHandler,
+    &RwLock<Stats>,
+    &Commands,
+);
+
+fn new_column<T: 'static + Send + Sync>() -> Box<dyn Column> {
+    Box::new(Vec::<T>::new())
+}
+
+// Components are handed to `run()` and `update()` as a `Mut`, so only the ones they write to
+// are marked changed.
+fn run_column<T: 'static + Run + Send + Sync>(
+    column: &mut dyn Column,
+    ticks: &mut [ComponentTicks],
+    change_tick: u32,
+    storage: &RwLock<Storage>,
+    commands: &Commands,
+) {
+    let components = column
+        .as_any_mut()
+        .downcast_mut::<Vec<T>>()
+        .expect("Column type does not match its ComponentInfo");
+
+    components
+        .par_iter_mut()
+        .zip(ticks.par_iter_mut())
+        .for_each(|(component, ticks)| {
+            T::run(
+                Mut::new(component, ticks, change_tick),
+                &storage.read().unwrap(),
+                commands,
+            );
+        })
+}
+
+fn update_column<T: 'static + Update + Send + Sync>(
+    column: &mut dyn Column,
+    ticks: &mut [ComponentTicks],
+    change_tick: u32,
+    storage: &RwLock<Storage>,
+    input: &InputHandler,
+    stats: &RwLock<Stats>,
+    commands: &Commands,
+) {
+    let components = column
+        .as_any_mut()
+        .downcast_mut::<Vec<T>>()
+        .expect("Column type does not match its ComponentInfo");
+
+    components
+        .par_iter_mut()
+        .zip(ticks.par_iter_mut())
+        .for_each(|(component, ticks)| {
+            T::update(
+                Mut::new(component, ticks, change_tick),
+                storage,
+                input,
+                stats,
+                commands,
+            );
+        })
+}
+
+/// Everything the world needs to know about a component type without knowing the type itself:
+/// how to create an empty column for it, and how to run its `Run`/`Update` implementations, if
+/// it has any.
+pub struct ComponentInfo {
+    pub type_id: TypeId,
+    pub label: &'static str,
+    pub type_name: &'static str,
+    new_column: fn() -> Box<dyn Column>,
+    run: Option<RunFn>,
+    update: Option<UpdateFn>,
+    // `Stats` labels, built once so that recording them does not allocate on every tick
+    pub(crate) run_sector: String,
+    pub(crate) update_sector: String,
+    pub(crate) count_value: String,
+}
+
+impl ComponentInfo {
+    /// A component with `Run` and `Update` implementations, leaving out the no-op ones
+    /// generated by `#[derive(Component)]`.
+    pub fn new<T: 'static + Run + Update + Send + Sync>(label: &'static str) -> Self {
+        let mut info = Self::data::<T>(label);
+
+        if !<T as Run>::NOOP {
+            info = info.with_run::<T>();
+        }
+        if !<T as Update>::NOOP {
+            info = info.with_update::<T>();
+        }
+
+        info
+    }
+
+    /// A component that is only stored, never run or updated.
+    pub fn data<T: 'static + Send + Sync>(label: &'static str) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            label,
+            type_name: std::any::type_name::<T>(),
+            new_column: new_column::<T>,
+            run: None,
+            update: None,
+            run_sector: format!("run(): {}", label),
+            update_sector: format!("update(): {}", label),
+            count_value: format!("{} count", label),
+        }
+    }
+
+    /// Runs the component's `Run` implementation every tick.
+    pub fn with_run<T: 'static + Run + Send + Sync>(mut self) -> Self {
+        assert_eq!(
+            self.type_id,
+            TypeId::of::<T>(),
+            "ComponentInfo::with_run() - wrong type"
+        );
+        self.run = Some(run_column::<T>);
+        self
+    }
+
+    /// Runs the component's `Update` implementation every tick.
+    pub fn with_update<T: 'static + Update + Send + Sync>(mut self) -> Self {
+        assert_eq!(
+            self.type_id,
+            TypeId::of::<T>(),
+            "ComponentInfo::with_update() - wrong type"
+        );
+        self.update = Some(update_column::<T>);
+        self
+    }
+
+    /// Whether the world calls `run()` on this component every tick.
+    pub fn runs(&self) -> bool {
+        self.run.is_some()
+    }
+
+    /// Whether the world calls `update()` on this component every tick.
+    pub fn updates(&self) -> bool {
+        self.update.is_some()
+    }
+
+    pub fn new_column(&self) -> Box<dyn Column> {
+        (self.new_column)()
+    }
+
+    pub fn run(
+        &self,
+        (column, ticks): (&mut dyn Column, &mut [ComponentTicks]),
+        change_tick: u32,
+        storage: &RwLock<Storage>,
+        commands: &Commands,
+    ) {
+        if let Some(run) = self.run {
+            run(column, ticks, change_tick, storage, commands);
+        }
+    }
+
+    pub fn update(
+        &self,
+        (column, ticks): (&mut dyn Column, &mut [ComponentTicks]),
+        change_tick: u32,
+        storage: &RwLock<Storage>,
+        input: &InputHandler,
+        stats: &RwLock<Stats>,
+        commands: &Commands,
+    ) {
+        if let Some(update) = self.update {
+            update(column, ticks, change_tick, storage, input, stats, commands);
+        }
+    }
+}
+
+/// Registry of every component type the world has seen, in registration order.
+#[derive(Default)]
+pub struct Components {
+    infos: Vec<ComponentInfo>,
+    index: HashMap<TypeId, usize>,
+    hooks: HashMap<TypeId, Hooks>, // only types registered with `World::register_hooks()`
+}
+
+impl Components {
+    pub fn get(&self, type_id: TypeId) -> Option<&ComponentInfo> {
+        self.index.get(&type_id).map(|&idx| &self.infos[idx])
+    }
+
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.index.contains_key(&type_id)
+    }
+
+    /// Registers the component's type, described by `component`, unless it is already known.
+    pub fn register_component<T: Component>(&mut self, component: &mut T) {
+        if !self.contains(TypeId::of::<T>()) {
+            self.register(component.component_info());
+        }
+    }
+
+    /// Registers the component type unless it is already known.
+    pub fn register(&mut self, info: ComponentInfo) {
+        if self.index.contains_key(&info.type_id) {
+            return;
+        }
+
+        if let Some(other) = self.infos.iter().find(|other| other.label == info.label) {
+            warn!(
+                "Components::register() - {} and {} share the label {}",
+                other.type_name, info.type_name, info.label
+            );
+        }
+
+        debug!(
+            "Components::register() - registered component: {} ({})",
+            info.label, info.type_name
+        );
+
+        self.index.insert(info.type_id, self.infos.len());
+        self.infos.push(info);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.infos.iter()
+    }
+
+    pub(crate) fn set_hooks(&mut self, type_id: TypeId, hooks: Hooks) {
+        self.hooks.insert(type_id, hooks);
+    }
+
+    pub(crate) fn hooks(&self, type_id: TypeId) -> Option<Hooks> {
+        self.hooks.get(&type_id).copied()
+    }
+
+    /// Whether any of the component types has hooks.
+    pub(crate) fn any_hooks(&self, types: &[TypeId]) -> bool {
+        !self.hooks.is_empty() && types.iter().any(|type_id| self.hooks.contains_key(type_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+}
+
+/// Holds an archetype's column, and the change ticks of each of its rows, so that systems
+/// running in parallel can each borrow a different column of the same archetype mutably,
+/// through a shared `&World`.
+struct ColumnCell {
+    column: UnsafeCell<Box<dyn Column>>,
+    ticks: UnsafeCell<Vec<ComponentTicks>>,
+}
+
+// SAFETY: `Column` is `Send + Sync`. Unsynchronized access only happens through the
+// `*_unchecked_mut()` methods, whose callers guarantee exclusive access to the column.
+unsafe impl Sync for ColumnCell {}
+
+impl ColumnCell {
+    fn new(column: Box<dyn Column>) -> Self {
+        Self {
+            column: UnsafeCell::new(column),
+            ticks: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    fn get(&self) -> &dyn Column {
+        // SAFETY: mutable access through a shared reference is only handed out by
+        // `get_unchecked_mut()`, whose callers guarantee no other reference exists.
+        unsafe { (*self.column.get()).as_ref() }
+    }
+
+    fn get_mut(&mut self) -> &mut dyn Column {
+        self.column.get_mut().as_mut()
+    }
+
+    /// # Safety
+    ///
+    /// No other reference to this column may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_unchecked_mut(&self) -> &mut dyn Column {
+        (*self.column.get()).as_mut()
+    }
+
+    fn ticks(&self) -> &[ComponentTicks] {
+        // SAFETY: see `get()`.
+        unsafe { &*self.ticks.get() }
+    }
+
+    fn ticks_mut(&mut self) -> &mut Vec<ComponentTicks> {
+        self.ticks.get_mut()
+    }
+
+    /// # Safety
+    ///
+    /// No other reference to this column's ticks may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn ticks_unchecked_mut(&self) -> &mut Vec<ComponentTicks> {
+        &mut *self.ticks.get()
+    }
+
+    fn get_with_ticks_mut(&mut self) -> (&mut dyn Column, &mut [ComponentTicks]) {
+        (self.column.get_mut().as_mut(), self.ticks.get_mut())
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) {
+        self.get_mut().swap_remove_drop(row);
+        self.ticks_mut().swap_remove(row);
+    }
+
+    fn swap_remove_take<T: 'static>(&mut self, row: usize) -> T {
+        self.ticks_mut().swap_remove(row);
+        self.get_mut()
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Column type does not match the taken component")
+            .swap_remove(row)
+    }
+
+    fn swap_remove_into(&mut self, row: usize, other: &mut ColumnCell) {
+        self.get_mut().swap_remove_into(row, other.get_mut());
+        let ticks = self.ticks_mut().swap_remove(row);
+        other.ticks_mut().push(ticks);
+    }
+}
+
+/// Where an entity's components live: which archetype, and which row of its columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityLocation {
+    pub archetype: usize,
+    pub row: usize,
+}
+
+/// A table holding every entity that has exactly the same set of component types.
+///
+/// Each component type gets a dense column, and row `n` of every column belongs to
+/// `entities[n]`, so iterating a set of components never has to skip over holes.
+pub struct Archetype {
+    id: usize,
+    types: Vec<TypeId>, // sorted, identifies the archetype
+    columns: HashMap<TypeId, ColumnCell>,
+    entities: Vec<Entity>,
+    add_edges: HashMap<TypeId, usize>, // cached archetype reached by adding a type
+    remove_edges: HashMap<TypeId, usize>, // cached archetype reached by removing a type
+}
+
+impl Archetype {
+    fn new(id: usize, types: Vec<TypeId>, components: &Components) -> Self {
+        let columns = types
+            .iter()
+            .map(|type_id| {
+                let info = components
+                    .get(*type_id)
+                    .expect("Archetype::new() - component type was not registered");
+                (*type_id, ColumnCell::new(info.new_column()))
+            })
+            .collect();
+
+        Self {
+            id,
+            types,
+            columns,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.columns.contains_key(&type_id)
+    }
+
+    pub fn column(&self, type_id: TypeId) -> Option<&dyn Column> {
+        self.columns.get(&type_id).map(|column| column.get())
+    }
+
+    /// Mutable access to a column. Rows must not be added or removed through it, or the
+    /// column would fall out of step with the archetype's entities and change ticks.
+    pub(crate) fn column_mut(&mut self, type_id: TypeId) -> Option<&mut dyn Column> {
+        match self.columns.get_mut(&type_id) {
+            Some(column) => Some(column.get_mut()),
+            None => None,
+        }
+    }
+
+    /// A column together with the change ticks of its rows.
+    pub(crate) fn column_with_ticks_mut(
+        &mut self,
+        type_id: TypeId,
+    ) -> Option<(&mut dyn Column, &mut [ComponentTicks])> {
+        self.columns
+            .get_mut(&type_id)
+            .map(|column| column.get_with_ticks_mut())
+    }
+
+    /// The change ticks of every row of the column holding `type_id`.
+    pub fn ticks(&self, type_id: TypeId) -> Option<&[ComponentTicks]> {
+        self.columns.get(&type_id).map(|column| column.ticks())
+    }
+
+    /// # Safety
+    ///
+    /// No other reference to the column's ticks may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn ticks_unchecked_mut(
+        &self,
+        type_id: TypeId,
+    ) -> Option<&mut Vec<ComponentTicks>> {
+        self.columns
+            .get(&type_id)
+            .map(|column| column.ticks_unchecked_mut())
+    }
+
+    /// Mutable access to a column through a shared reference to the archetype.
+    ///
+    /// # Safety
+    ///
+    /// No other reference to this column may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn column_unchecked_mut(&self, type_id: TypeId) -> Option<&mut dyn Column> {
+        self.columns
+            .get(&type_id)
+            .map(|column| column.get_unchecked_mut())
+    }
+
+    /// Typed access to the column holding `T`.
+    pub fn components<T: 'static>(&self) -> Option<&Vec<T>> {
+        self.column(TypeId::of::<T>())
+            .and_then(|column| column.as_any().downcast_ref::<Vec<T>>())
+    }
+
+    /// The component of type `T` in `row`, together with its change ticks.
+    pub(crate) fn get_with_ticks_mut<T: 'static>(
+        &mut self,
+        row: usize,
+    ) -> Option<(&mut T, &mut ComponentTicks)> {
+        let (column, ticks) = self
+            .columns
+            .get_mut(&TypeId::of::<T>())?
+            .get_with_ticks_mut();
+        let component = column.as_any_mut().downcast_mut::<Vec<T>>()?.get_mut(row)?;
+
+        Some((component, ticks.get_mut(row)?))
+    }
+
+    /// Typed mutable access to the column holding `T`. Writes through it are not tracked by
+    /// change detection.
+    pub fn components_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
+        self.column_mut(TypeId::of::<T>())
+            .and_then(|column| column.as_any_mut().downcast_mut::<Vec<T>>())
+            .map(|vec| vec.as_mut_slice())
+    }
+
+    /// Appends a component for the entity most recently moved into this archetype.
+    ///
+    /// Only for `Bundle` implementations, including derived ones.
+    ///
+    /// # Safety
+    ///
+    /// Every column must get exactly one component per entity before the archetype is queried,
+    /// since queries read each column up to the archetype's length without bounds checks.
+    #[doc(hidden)]
+    pub unsafe fn push_component<T: 'static>(&mut self, component: T, change_tick: u32) {
+        let column = self
+            .columns
+            .get_mut(&TypeId::of::<T>())
+            .expect("Archetype is missing the pushed component's column");
+
+        column
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Column type does not match the pushed component")
+            .push(component);
+        column.ticks_mut().push(ComponentTicks::new(change_tick));
+    }
+
+    /// Overwrites the component in `row` and marks it changed.
+    pub(crate) fn replace_component<T: 'static>(
+        &mut self,
+        row: usize,
+        component: T,
+        change_tick: u32,
+    ) {
+        let column = self
+            .columns
+            .get_mut(&TypeId::of::<T>())
+            .expect("Archetype is missing the replaced component's column");
+
+        column
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Column type does not match the replaced component")[row] = component;
+        column.ticks_mut()[row].set_changed(change_tick);
+    }
+
+    /// Reserves room for `additional` more entities in every column.
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        for column in self.columns.values_mut() {
+            column.get_mut().reserve(additional);
+            column.ticks_mut().reserve(additional);
+        }
+    }
+
+    /// Appends an entity and returns its row. Unless this is the empty archetype, the caller
+    /// must push one component onto every column right after.
+    pub(crate) fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
+    /// Removes the row, dropping its components. Returns the entity that was swapped into the
+    /// row, if any, so its location can be patched.
+    fn remove_row(&mut self, row: usize) -> Option<Entity> {
+        for column in self.columns.values_mut() {
+            column.swap_remove_drop(row);
+        }
+
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// All archetypes of a world. The archetype at index `0` has no components; freshly spawned
+/// entities start there.
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    index: HashMap<Vec<TypeId>, usize>,
+}
+
+impl Archetypes {
+    pub const EMPTY: usize = 0;
+
+    pub fn new() -> Self {
+        let components = Components::default();
+        let empty = Archetype::new(Self::EMPTY, Vec::new(), &components);
+
+        let mut index = HashMap::new();
+        index.insert(Vec::new(), Self::EMPTY);
+
+        Self {
+            archetypes: vec![empty],
+            index,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archetypes.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Archetype> {
+        self.archetypes.get(id)
+    }
+
+    pub(crate) fn get_mut(&mut self, id: usize) -> Option<&mut Archetype> {
+        self.archetypes.get_mut(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Archetype> {
+        self.archetypes.iter_mut()
+    }
+
+    /// Places a freshly spawned entity in the empty archetype.
+    pub fn spawn(&mut self, entity: Entity) -> EntityLocation {
+        let row = self.archetypes[Self::EMPTY].push_entity(entity);
+
+        EntityLocation {
+            archetype: Self::EMPTY,
+            row,
+        }
+    }
+
+    /// Returns the archetype with exactly the given component types, creating it if needed.
+    pub fn get_or_insert(&mut self, mut types: Vec<TypeId>, components: &Components) -> usize {
+        types.sort();
+        types.dedup();
+
+        if let Some(&id) = self.index.get(&types) {
+            return id;
+        }
+
+        let id = self.archetypes.len();
+        self.archetypes
+            .push(Archetype::new(id, types.clone(), components));
+        self.index.insert(types, id);
+
+        debug!("Archetypes::get_or_insert() - created archetype {}", id);
+
+        id
+    }
+
+    /// Returns the archetype reached by adding `type_id` to `source`.
+    pub fn with_type(&mut self, source: usize, type_id: TypeId, components: &Components) -> usize {
+        if let Some(&target) = self.archetypes[source].add_edges.get(&type_id) {
+            return target;
+        }
+
+        let mut types = self.archetypes[source].types.clone();
+        types.push(type_id);
+        let target = self.get_or_insert(types, components);
+
+        self.archetypes[source].add_edges.insert(type_id, target);
+        self.archetypes[target].remove_edges.insert(type_id, source);
+
+        target
+    }
+
+    /// Returns the archetype reached by removing `type_id` from `source`.
+    pub fn without_type(
+        &mut self,
+        source: usize,
+        type_id: TypeId,
+        components: &Components,
+    ) -> usize {
+        if let Some(&target) = self.archetypes[source].remove_edges.get(&type_id) {
+            return target;
+        }
+
+        let mut types = self.archetypes[source].types.clone();
+        types.retain(|t| *t != type_id);
+        let target = self.get_or_insert(types, components);
+
+        self.archetypes[source].remove_edges.insert(type_id, target);
+        self.archetypes[target].add_edges.insert(type_id, source);
+
+        target
+    }
+
+    /// Returns mutable references to two different archetypes.
+    fn pair_mut(&mut self, a: usize, b: usize) -> (&mut Archetype, &mut Archetype) {
+        assert_ne!(
+            a, b,
+            "Archetypes::pair_mut() called with the same archetype twice"
+        );
+
+        if a < b {
+            let (left, right) = self.archetypes.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.archetypes.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    /// Moves the entity at `location` into `target`, carrying over every component both
+    /// archetypes share, along with their change ticks, and dropping the rest. Components that
+    /// only exist in `target` must be pushed by the caller right after.
+    ///
+    /// Returns the entity's new row and the entity that was swapped into its old row, if any.
+    pub(crate) fn move_entity(
+        &mut self,
+        location: EntityLocation,
+        target: usize,
+    ) -> (usize, Option<Entity>) {
+        self.move_entity_with(location, target, |_, column, row| {
+            column.swap_remove_drop(row)
+        })
+    }
+
+    /// Like `move_entity()`, but hands back the entity's component of type `T` instead of
+    /// dropping it. `target` must not have a `T` column, while the source archetype must.
+    pub(crate) fn move_entity_take<T: 'static>(
+        &mut self,
+        location: EntityLocation,
+        target: usize,
+    ) -> (T, usize, Option<Entity>) {
+        let mut taken = None;
+
+        let (row, swapped) = self.move_entity_with(location, target, |type_id, column, row| {
+            if type_id == TypeId::of::<T>() {
+                taken = Some(column.swap_remove_take::<T>(row));
+            } else {
+                column.swap_remove_drop(row);
+            }
+        });
+
+        let component = taken.expect("Archetypes::move_entity_take() - source has no such column");
+
+        (component, row, swapped)
+    }
+
+    /// Moves the entity, calling `remove` for every column `target` does not share.
+    fn move_entity_with(
+        &mut self,
+        location: EntityLocation,
+        target: usize,
+        mut remove: impl FnMut(TypeId, &mut ColumnCell, usize),
+    ) -> (usize, Option<Entity>) {
+        let (source, target) = self.pair_mut(location.archetype, target);
+
+        for (type_id, column) in source.columns.iter_mut() {
+            match target.columns.get_mut(type_id) {
+                Some(target_column) => column.swap_remove_into(location.row, target_column),
+                None => remove(*type_id, column, location.row),
+            }
+        }
+
+        let entity = source.entities.swap_remove(location.row);
+        let swapped = source.entities.get(location.row).copied();
+
+        target.entities.push(entity);
+
+        (target.entities.len() - 1, swapped)
+    }
+
+    /// Removes the entity at `location`, dropping all of its components. Returns the entity
+    /// that was swapped into its row, if any.
+    pub(crate) fn remove(&mut self, location: EntityLocation) -> Option<Entity> {
+        self.archetypes[location.archetype].remove_row(location.row)
+    }
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,69 @@
+use std::any::TypeId;
+
+use crate::{
+    archetypes::{Archetype, Components},
+    Component,
+};
+
+/// A set of components spawned together, with `World::spawn_with()` or `World::spawn_batch()`.
+/// Implemented for tuples of up to eight components, all of different types, and derived for
+/// structs whose fields are all components with `#[derive(Bundle)]` from `pixpox_ecs_macros`.
+///
+/// ## Example
+///
+/// ```ignore
+/// let cells = (0..height).flat_map(|y| (0..width).map(move |x| (Cell::new((x, y)), Heat(0))));
+///
+/// let entities = world.spawn_batch(cells);
+///
+/// #[derive(Bundle)]
+/// struct Player {
+///     position: Position,
+///     velocity: Velocity,
+///     sprite: Sprite,
+/// }
+///
+/// let player = world.spawn_with(Player { position, velocity, sprite });
+/// ```
+pub trait Bundle: 'static + Send + Sync {
+    /// Type ids of the bundle's components, in declaration order.
+    fn type_ids() -> Vec<TypeId>;
+
+    /// Registers every component type of the bundle, labelled by this bundle's components.
+    fn register(&mut self, components: &mut Components);
+
+    /// Pushes each component onto its column of `archetype`.
+    ///
+    /// # Safety
+    ///
+    /// `archetype` must hold exactly the bundle's component types, and an entity must have
+    /// just been pushed onto it without its components.
+    unsafe fn push(self, archetype: &mut Archetype, change_tick: u32);
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: Component),+> Bundle for ($($name,)+) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$name>()),+]
+            }
+
+            fn register(&mut self, components: &mut Components) {
+                $(components.register_component(&mut self.$index);)+
+            }
+
+            unsafe fn push(self, archetype: &mut Archetype, change_tick: u32) {
+                $(unsafe { archetype.push_component(self.$index, change_tick) };)+
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!(A 0);
+impl_bundle_for_tuple!(A 0, B 1);
+impl_bundle_for_tuple!(A 0, B 1, C 2);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_bundle_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
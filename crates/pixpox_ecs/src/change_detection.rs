@@ -0,0 +1,135 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+/// The world-wide change tick counter, shared between a `World` and its `Storage`.
+///
+/// It is bumped at the start of every `World::run()` and every time a system runs, and
+/// everything written is stamped with its current value. A system runs at the tick before the
+/// bump, so the current value is always newer than the last run of every system.
+#[derive(Debug, Clone)]
+pub struct ChangeTick(Arc<AtomicU32>);
+
+impl ChangeTick {
+    pub fn new() -> Self {
+        // Start at 1 so that everything added before the first tick is newer than tick 0.
+        Self(Arc::new(AtomicU32::new(1)))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Bumps the tick and returns the new value.
+    pub fn increment(&self) -> u32 {
+        self.0.fetch_add(1, Ordering::AcqRel).wrapping_add(1)
+    }
+}
+
+impl Default for ChangeTick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The ticks at which a component or bucket was added and last changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, ticks: Ticks) -> bool {
+        ticks.is_newer(self.added)
+    }
+
+    pub fn is_changed(&self, ticks: Ticks) -> bool {
+        ticks.is_newer(self.changed)
+    }
+
+    pub fn set_changed(&mut self, tick: u32) {
+        self.changed = tick;
+    }
+}
+
+/// The window a query or system looks at: everything stamped after `last_run` and up to
+/// `this_run` counts as added or changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ticks {
+    pub last_run: u32,
+    pub this_run: u32,
+}
+
+impl Ticks {
+    pub fn new(last_run: u32, this_run: u32) -> Self {
+        Self { last_run, this_run }
+    }
+
+    /// Returns `true` if `tick` is more recent than `last_run`. Comparing the distances to
+    /// `this_run` keeps this correct when the counter wraps around.
+    pub fn is_newer(&self, tick: u32) -> bool {
+        self.this_run.wrapping_sub(tick) < self.this_run.wrapping_sub(self.last_run)
+    }
+}
+
+/// A mutable reference to a component that marks it changed when it is written through.
+///
+/// Queries hand out `Mut<T>` for `&mut T`, so `Changed<T>` only matches components that were
+/// actually modified, not every component a query could have modified.
+pub struct Mut<'w, T> {
+    value: &'w mut T,
+    ticks: &'w mut ComponentTicks,
+    this_run: u32,
+}
+
+impl<'w, T> Mut<'w, T> {
+    pub(crate) fn new(value: &'w mut T, ticks: &'w mut ComponentTicks, this_run: u32) -> Self {
+        Self {
+            value,
+            ticks,
+            this_run,
+        }
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        *self.ticks
+    }
+
+    /// Returns the inner `&mut T` after marking the component changed.
+    pub fn into_inner(self) -> &'w mut T {
+        self.ticks.set_changed(self.this_run);
+        self.value
+    }
+
+    /// Writes through without marking the component changed.
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'w, T> Deref for Mut<'w, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'w, T> DerefMut for Mut<'w, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.set_changed(self.this_run);
+        self.value
+    }
+}
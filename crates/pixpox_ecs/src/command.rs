@@ -0,0 +1,300 @@
+use std::{any::TypeId, sync::Mutex};
+
+use log::debug;
+
+use crate::{entity::Entity, Component, World};
+
+type Inserter = Box<dyn FnOnce(&mut World, Entity) + Send>;
+
+pub(crate) enum Command {
+    Spawn(Vec<Inserter>),
+    Despawn(Entity),
+    Insert(Entity, Inserter),
+    Remove(Entity, TypeId),
+    Custom(Box<dyn FnOnce(&mut World) + Send>),
+}
+
+/// # Commands
+///
+/// A thread-safe queue of structural changes to the world: spawning and despawning entities,
+/// and adding or removing components.
+///
+/// `Run` and `Update` implementations execute in parallel while the world's entities are
+/// borrowed, so they cannot change the world directly. Instead they record commands, which
+/// `World::run()` applies in the order they were queued once the update phase has finished.
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::RwLock;
+///
+/// use pixpox_ecs::{entity::Entity, Commands, Component, InputHandler, Mut, Stats, Storage, Update};
+///
+/// #[derive(Component)]
+/// struct Ash {
+///     pos: (u32, u32),
+/// }
+///
+/// #[derive(Component)]
+/// #[component(update)]
+/// struct Cell {
+///     entity: Entity,
+///     pos: (u32, u32),
+///     heat: u32,
+/// }
+///
+/// impl Update for Cell {
+///     fn update(
+///         this: Mut<Self>,
+///         _storage: &RwLock<Storage>,
+///         _input: &InputHandler,
+///         _stats: &RwLock<Stats>,
+///         commands: &Commands,
+///     ) {
+///         if this.heat == 0 {
+///             commands.despawn(this.entity);
+///             commands.spawn().with(Ash { pos: this.pos });
+///         }
+///     }
+/// }
+/// ```
+pub struct Commands {
+    queue: Mutex<Vec<Command>>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn push(&self, command: Command) {
+        self.queue
+            .lock()
+            .expect("Could not lock command queue")
+            .push(command);
+    }
+
+    /// Queues a new entity. Components added with `SpawnCommand::with()` are inserted when the
+    /// entity is spawned; the command is queued when the returned builder is dropped.
+    pub fn spawn(&self) -> SpawnCommand<'_> {
+        SpawnCommand {
+            commands: self,
+            inserters: Vec::new(),
+        }
+    }
+
+    /// Queues the removal of the entity and all its components.
+    pub fn despawn(&self, entity: Entity) {
+        self.push(Command::Despawn(entity));
+    }
+
+    /// Queues adding (or replacing) a component on an existing entity.
+    pub fn insert<ComponentType: Component>(&self, entity: Entity, component: ComponentType) {
+        self.push(Command::Insert(
+            entity,
+            Box::new(move |world, entity| world.add_component_to_entity(entity, component)),
+        ));
+    }
+
+    /// Queues the removal of the entity's component of type `T`.
+    pub fn remove<T: 'static>(&self, entity: Entity) {
+        self.push(Command::Remove(entity, TypeId::of::<T>()));
+    }
+
+    /// Queues the removal of the entity and all its descendants, see
+    /// `World::despawn_recursive()`.
+    pub fn despawn_recursive(&self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn_recursive(entity);
+        });
+    }
+
+    /// Queues attaching `child` to `parent`, see `World::set_parent()`.
+    pub fn set_parent(&self, child: Entity, parent: Entity) {
+        self.add(move |world| {
+            world.set_parent(child, parent);
+        });
+    }
+
+    /// Queues detaching `child` from its parent, see `World::remove_parent()`.
+    pub fn remove_parent(&self, child: Entity) {
+        self.add(move |world| {
+            world.remove_parent(child);
+        });
+    }
+
+    /// Queues an arbitrary change to the world.
+    pub fn add(&self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.push(Command::Custom(Box::new(command)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue
+            .lock()
+            .expect("Could not lock command queue")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes every queued command, leaving the queue empty.
+    pub(crate) fn take(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.queue.lock().expect("Could not lock command queue"))
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder returned by `Commands::spawn()`. The spawn is queued when it is dropped.
+pub struct SpawnCommand<'a> {
+    commands: &'a Commands,
+    inserters: Vec<Inserter>,
+}
+
+impl<'a> SpawnCommand<'a> {
+    pub fn with<ComponentType: Component>(mut self, component: ComponentType) -> Self {
+        self.inserters.push(Box::new(move |world, entity| {
+            world.add_component_to_entity(entity, component)
+        }));
+        self
+    }
+}
+
+impl<'a> Drop for SpawnCommand<'a> {
+    fn drop(&mut self) {
+        let inserters = std::mem::take(&mut self.inserters);
+        self.commands.push(Command::Spawn(inserters));
+    }
+}
+
+/// Applies the commands to the world, in the order they were queued.
+pub(crate) fn apply(queue: Vec<Command>, world: &mut World) {
+    if queue.is_empty() {
+        return;
+    }
+
+    debug!("Commands::apply() - applying {} commands", queue.len());
+
+    for command in queue {
+        match command {
+            Command::Spawn(inserters) => {
+                let entity = world.spawn();
+                for inserter in inserters {
+                    inserter(world, entity);
+                }
+            },
+            Command::Despawn(entity) => {
+                world.despawn(entity);
+            },
+            Command::Insert(entity, inserter) => inserter(world, entity),
+            Command::Remove(entity, type_id) => {
+                world.remove_component_by_id(entity, type_id);
+            },
+            Command::Custom(command) => command(world),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, RwLock};
+
+    use pixpox_utils::{InputHandler, Stats};
+
+    use super::*;
+    use crate::{
+        testing::{self, Name, NoPixelMap, Position, Velocity},
+        Mut, Stage, Storage, System, Update,
+    };
+
+    /// Queues its commands on the first update only.
+    #[derive(Component)]
+    #[component(label = "Queuer", update)]
+    struct Queuer {
+        target: Entity,
+        victim: Entity,
+        queued: bool,
+    }
+
+    impl Update for Queuer {
+        fn update(
+            mut this: Mut<Self>,
+            _storage: &RwLock<Storage>,
+            _input: &InputHandler,
+            _stats: &RwLock<Stats>,
+            commands: &Commands,
+        ) {
+            if this.queued {
+                return;
+            }
+            this.queued = true;
+
+            commands.spawn().with(Name("spawned".to_string()));
+            commands.insert(this.target, Position(5, 5));
+            commands.insert(this.target, Velocity(1, 1));
+            commands.remove::<Position>(this.target);
+            commands.despawn(this.victim);
+            commands.insert(this.victim, Velocity(2, 2));
+            commands.remove::<Position>(this.victim);
+        }
+    }
+
+    fn count_names(stage: Stage, counts: &Arc<Mutex<Vec<(Stage, usize)>>>) -> System {
+        let counts = Arc::clone(counts);
+        System::new(stage.name(), move |ctx| {
+            let names = ctx.query::<&Name>().len();
+            counts.lock().unwrap().push((stage, names));
+        })
+        .reads::<Name>()
+        .in_stage(stage)
+    }
+
+    #[test]
+    fn update_commands_are_applied_at_the_sync_point_in_order() {
+        let mut world = testing::world();
+        let target = world.spawn_with((Position(0, 0),));
+        let victim = world.spawn_with((Position(1, 1),));
+        world.spawn_with((Queuer {
+            target,
+            victim,
+            queued: false,
+        },));
+
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        world.add_system(count_names(Stage::PostUpdate, &counts));
+        world.add_system(count_names(Stage::PreRender, &counts));
+
+        world.run::<NoPixelMap>();
+
+        // Nothing happens before the sync point that follows `PostUpdate`
+        assert_eq!(
+            *counts.lock().unwrap(),
+            vec![(Stage::PostUpdate, 0), (Stage::PreRender, 1)]
+        );
+        assert!(world.commands.is_empty());
+
+        let spawned = world.query::<&Name>();
+        assert_eq!(spawned.len(), 1);
+        assert_eq!(spawned[0].1 .0, "spawned");
+
+        // Applied in order: the insert lands first, so the remove that follows takes it away
+        assert!(!world.has_component::<Position>(target));
+        assert_eq!(
+            world.get_component::<Velocity>(target),
+            Some(&Velocity(1, 1))
+        );
+
+        // Commands on an entity despawned earlier in the queue are skipped
+        assert!(!world.is_alive(victim));
+        assert!(world.get_component::<Velocity>(victim).is_none());
+        assert_eq!(world.entities().living_entity_count, 3);
+    }
+}
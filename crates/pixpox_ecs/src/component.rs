@@ -1,6 +1,6 @@
 use std::sync::RwLock;
 
-use crate::{Storage};
+use crate::{archetypes::ComponentInfo, Commands, Mut, Storage};
 use pixpox_utils::{InputHandler, Stats};
 use pixpox_common::Camera;
 
@@ -10,13 +10,15 @@ use pixpox_common::Camera;
 /// ### Example
 ///
 /// ```
+/// use pixpox_ecs::Label;
+///
 /// struct Cell {
 ///     label: &'static str,
 /// }
 ///
 /// impl Label for Cell {
-///    fn label(&mut self) -> &'static str {
-///       return self.label;
+///     fn label(&mut self) -> &'static str {
+///         self.label
 ///     }
 /// }
 /// ```
@@ -25,52 +27,128 @@ pub trait Label {
 }
 
 
+/// Anything that can be added to an entity.
+///
+/// Every type implementing `Label`, `Run` and `Update` is a component, and has its `run()` and
+/// `update()` called every tick. `#[derive(Component)]` writes those three for you. Pure data,
+/// which the world only stores and hands to queries and systems, implements `Component`
+/// directly and skips `Run` and `Update` altogether.
+///
+/// ### Example
+///
+/// ```ignore
+/// struct Velocity(f32, f32);
+///
+/// impl Component for Velocity {
+///     fn component_info(&mut self) -> ComponentInfo {
+///         ComponentInfo::data::<Self>("Velocity")
+///     }
+/// }
+/// ```
+pub trait Component: 'static + Send + Sync {
+    /// Describes the type to the world. Called on the first value of the type that is added.
+    fn component_info(&mut self) -> ComponentInfo;
+}
+
+impl<T: 'static + Label + Run + Update + Send + Sync> Component for T {
+    fn component_info(&mut self) -> ComponentInfo {
+        ComponentInfo::new::<T>(self.label())
+    }
+}
+
 /// The `Run` trait specifies a `run()` method that is executed for each component 
 /// whenever `world.run()` is called. This function is parallelized using multi-threading 
 /// and only has read access to the storage. It is designed for heavy computation, and no 
-/// updates are allowed.
+/// updates are allowed. Structural changes to the world (spawning, despawning, adding or
+/// removing components) can be queued on `commands`, and are applied after the update phase.
+/// The component is handed over as a `Mut`, so it is only marked changed for `Changed<T>`
+/// filters if `run()` actually writes to it.
 /// 
 /// ### Example
 ///
 /// ```
+/// use std::collections::HashMap;
+///
+/// use pixpox_ecs::{Commands, Mut, Run, Storage};
+///
 /// struct Cell {
-///     label: &'static str,
+///     pos: (u32, u32),
+///     alive: bool,
 /// }
 ///
 /// impl Run for Cell {
-///     fn run(&mut self, storage: &Storage) {
+///     fn run(mut this: Mut<Self>, storage: &Storage, _commands: &Commands) {
 ///         let grid = storage
-///             .query_storage::<HashMap<LogicalPosition<u32>, bool>>("grid")
+///             .query_storage::<HashMap<(u32, u32), bool>>("grid")
 ///             .expect("Could not query storage: grid");
+///
+///         // Only write, and mark the cell changed, if it flipped
+///         let alive = grid[&this.pos];
+///         if this.alive != alive {
+///             this.alive = alive;
+///         }
 ///     }
 /// }
-///
 /// ```
-pub trait Run {
-    fn run(&mut self, storage: &Storage);
+pub trait Run: Sized {
+    /// Set by the no-op `run()` that `#[derive(Component)]` generates, so it is never scheduled.
+    #[doc(hidden)]
+    const NOOP: bool = false;
+
+    fn run(this: Mut<Self>, storage: &Storage, commands: &Commands);
 }
 
 /// The `Update` trait specifies an `update()` method, which much like `run()`  is 
 /// executed on every pass of `world.run()`. It is also parallelized, but its given a 
 /// `RwLock` instead of an immutable reference. This method is meant to update the 
-/// world when a change is present, and it should not attempt to obtain a `.write()` lock 
-/// on the storage when no changes are present, as doing so would adversely impact performance.
+/// world when a change is present. Components update in parallel, so take a `.read()` lock on
+/// the storage and then lock only the buckets you change with `write_storage()` or
+/// `lock_buckets()`; a `.write()` lock on the whole storage serializes every update.
+/// Like `run()`, it can queue structural changes to the world on `commands`, and only marks
+/// the component changed when it writes to it.
 /// 
 /// ### Example
 ///
 /// ```
+/// use std::sync::RwLock;
+///
+/// use pixpox_ecs::{Commands, InputHandler, Mut, Stats, Storage, Update};
+///
 /// struct Cell {
-///     label: &'static str,
+///     index: usize,
+///     alive: bool,
 /// }
 ///
 /// impl Update for Cell {
-///     fn update(&mut self, storage: &RwLock<Storage>) {
-///         let mut storage = storage.write().unwrap();
+///     fn update(
+///         this: Mut<Self>,
+///         storage: &RwLock<Storage>,
+///         _input: &InputHandler,
+///         _stats: &RwLock<Stats>,
+///         _commands: &Commands,
+///     ) {
+///         let storage = storage.read().unwrap();
+///         let mut grid = storage
+///             .write_storage::<Vec<bool>>("grid")
+///             .expect("Could not query storage: grid");
+///
+///         grid[this.index] = this.alive;
 ///     }
 /// }
 /// ```
-pub trait Update {
-    fn update(&mut self, storage: &RwLock<Storage>, input: &InputHandler, stats: &RwLock<Stats>);
+pub trait Update: Sized {
+    /// Set by the no-op `update()` that `#[derive(Component)]` generates, so it is never
+    /// scheduled.
+    #[doc(hidden)]
+    const NOOP: bool = false;
+
+    fn update(
+        this: Mut<Self>,
+        storage: &RwLock<Storage>,
+        input: &InputHandler,
+        stats: &RwLock<Stats>,
+        commands: &Commands,
+    );
 }
 
 /// The Texture trait defines how a component should be rendered.
@@ -79,18 +157,30 @@ pub trait Update {
 ///
 /// ### Example
 /// ```
-/// impl Texture for GlobalPixelMap {
+/// use pixpox_common::Camera;
+/// use pixpox_ecs::{InputHandler, Texture};
+///
+/// struct Sprite {
+///     pixels: Vec<[u8; 4]>,
+///     width: u32,
+///     height: u32,
+/// }
+///
+/// impl Texture for Sprite {
 ///     fn render(&self, pixels: &mut [u8]) {
-///         debug!("Rendering GlobalPixelMap");
-///         for (c, pix) in self.pixelmap
-///             .iter()
-///             .zip(pixels.chunks_exact_mut(4)) {
-///                 pix.copy_from_slice(c);
+///         for (c, pix) in self.pixels.iter().zip(pixels.chunks_exact_mut(4)) {
+///             pix.copy_from_slice(c);
 ///         }
 ///     }
 ///
+///     fn update(&mut self, _input: &InputHandler) {}
+///
 ///     fn size(&self) -> (u32, u32) {
-///         return (self.width, self.height);
+///         (self.width, self.height)
+///     }
+///
+///     fn get_camera(&self) -> Camera {
+///         Camera::new(0, 0, self.height, self.width, self.height, self.width)
 ///     }
 /// }
 /// ```
@@ -103,7 +193,108 @@ pub trait Texture {
 
 pub trait GlobalPixelMap {
     fn render(&self, pixels: &mut [u8]);
+    /// Handles input once per tick. Only meant to move the camera: the pixel map is only
+    /// marked changed, and rendered again, if the camera moved. Drawing goes through
+    /// `Storage::write_storage()`, which marks it changed.
     fn update(&mut self, input: &InputHandler);
     fn size(&self) -> (u32, u32);
     fn get_camera(&self) -> Camera;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{self, NoPixelMap},
+        Component,
+    };
+
+    #[derive(Component)]
+    struct Plain;
+
+    #[derive(Component)]
+    #[component(label = "Renamed")]
+    struct Labelled;
+
+    #[derive(Component)]
+    #[component(run)]
+    struct RunOnly(u32);
+
+    impl Run for RunOnly {
+        fn run(mut this: Mut<Self>, _storage: &Storage, _commands: &Commands) {
+            this.0 += 1;
+        }
+    }
+
+    #[derive(Component)]
+    #[component(label = "Both", run, update)]
+    struct Both {
+        runs: u32,
+        updates: u32,
+    }
+
+    impl Run for Both {
+        fn run(mut this: Mut<Self>, _storage: &Storage, _commands: &Commands) {
+            this.runs += 1;
+        }
+    }
+
+    impl Update for Both {
+        fn update(
+            mut this: Mut<Self>,
+            _storage: &RwLock<Storage>,
+            _input: &InputHandler,
+            _stats: &RwLock<Stats>,
+            _commands: &Commands,
+        ) {
+            this.updates += 1;
+        }
+    }
+
+    #[test]
+    fn derived_labels() {
+        assert_eq!(Plain.component_info().label, "Plain");
+        assert_eq!(Labelled.component_info().label, "Renamed");
+        assert_eq!(
+            Both {
+                runs: 0,
+                updates: 0
+            }
+            .component_info()
+            .label,
+            "Both"
+        );
+    }
+
+    #[test]
+    fn only_declared_hooks_are_scheduled() {
+        let plain = Plain.component_info();
+        assert!(!plain.runs() && !plain.updates());
+
+        let run_only = RunOnly(0).component_info();
+        assert!(run_only.runs() && !run_only.updates());
+
+        let both = Both {
+            runs: 0,
+            updates: 0,
+        }
+        .component_info();
+        assert!(both.runs() && both.updates());
+    }
+
+    #[test]
+    fn declared_hooks_run_every_tick() {
+        let mut world = testing::world();
+        let run_only = world.spawn_with((RunOnly(0),));
+        let both = world.spawn_with((Both {
+            runs: 0,
+            updates: 0,
+        },));
+
+        world.run::<NoPixelMap>();
+        world.run::<NoPixelMap>();
+
+        assert_eq!(world.get_component::<RunOnly>(run_only).unwrap().0, 2);
+        let both = world.get_component::<Both>(both).unwrap();
+        assert_eq!((both.runs, both.updates), (2, 2));
+    }
+}
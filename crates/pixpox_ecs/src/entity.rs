@@ -1,18 +1,39 @@
-use std::time::Instant;
+use std::{collections::HashSet, time::Instant};
 
 // max entities should be the size of the largest possible u32 value
 // const MAX_ENTITIES: usize = std::usize::MAX;
 
 use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+use crate::archetypes::EntityLocation;
+
+/// A handle to an entity living in a `World`.
+///
+/// `id` is the slot the entity occupies in every component vec, `generation` is bumped every
+/// time that slot is freed. A handle whose generation no longer matches its slot belongs to an
+/// entity that has been despawned, and is rejected instead of aliasing the slot's new owner.
+///
+/// Snapshots restore entities under their original ids, so handles stored inside serialized
+/// components stay valid after loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Entity {
     pub id: usize,
+    pub generation: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EntitySlot {
+    generation: u32,
+    alive: bool,
+    location: EntityLocation,
 }
 
 pub struct EntityManager {
     pub id_counter: usize,
     pub living_entity_count: usize, // number of living entities
+    slots: Vec<EntitySlot>,
+    free_list: Vec<usize>, // despawned slots waiting to be recycled
 }
 
 impl EntityManager {
@@ -21,31 +42,191 @@ impl EntityManager {
         Self {
             id_counter: 0,
             living_entity_count: 0,
+            slots: Vec::new(),
+            free_list: Vec::new(),
         }
     }
 
+    /// Creates a new entity, reusing the most recently freed slot if there is one.
     pub fn create(&mut self) -> Entity {
         let now = Instant::now();
 
-        let id = self.id_counter;
-        self.id_counter += 1;
+        let id = match self.free_list.pop() {
+            Some(id) => id,
+            None => {
+                let id = self.id_counter;
+                self.id_counter += 1;
+                self.slots.push(EntitySlot {
+                    generation: 0,
+                    alive: false,
+                    location: EntityLocation {
+                        archetype: 0,
+                        row: 0,
+                    },
+                });
+                id
+            },
+        };
+
+        let slot = &mut self.slots[id];
+        slot.alive = true;
+        let generation = slot.generation;
+
         self.living_entity_count += 1;
 
         debug!(
-            "EntityManager::create() - id: {}, living_entity_count: {}, in {} micros",
-            id, self.living_entity_count,
+            "EntityManager::create() - id: {}, generation: {}, living_entity_count: {}, in {} micros",
+            id, generation, self.living_entity_count,
             now.elapsed().as_micros().to_string()
         );
 
-        Entity { id }
+        Entity { id, generation }
+    }
+
+    /// Creates the entity under exactly this id and generation, e.g. when loading a snapshot.
+    /// Slots skipped over on the way are marked free. Returns `false` if the slot is taken.
+    pub(crate) fn create_at(&mut self, entity: Entity) -> bool {
+        self.grow_to(entity.id);
+
+        let slot = &mut self.slots[entity.id];
+        if slot.alive {
+            error!(
+                "EntityManager::create_at() - entity {} is already alive",
+                entity.id
+            );
+            return false;
+        }
+
+        slot.alive = true;
+        slot.generation = entity.generation;
+
+        self.free_list.retain(|&id| id != entity.id);
+        self.living_entity_count += 1;
+
+        true
+    }
+
+    /// The handles freed slots will be handed out as, in the reverse order `create()` reuses
+    /// them.
+    pub(crate) fn free_slots(&self) -> Vec<Entity> {
+        self.free_list
+            .iter()
+            .map(|&id| Entity {
+                id,
+                generation: self.slots[id].generation,
+            })
+            .collect()
     }
 
-    pub fn destroy(&mut self, entity: Entity) {
+    /// Rebuilds the free list from which slots are alive, e.g. when loading a snapshot. The
+    /// slots in `free` are handed out first, and as the same handles they would have been in
+    /// the saved world; every other dead slot, such as one this world freed beyond the
+    /// snapshot's ids, is reused after them.
+    pub(crate) fn restore_free_slots(&mut self, free: &[Entity]) {
+        for entity in free {
+            self.grow_to(entity.id);
+        }
+
+        // `create()` pops from the back, so the slots the snapshot does not know about go in
+        // front, with the lowest id nearest the back to be reused first.
+        let listed: HashSet<usize> = free.iter().map(|entity| entity.id).collect();
+        self.free_list = (0..self.slots.len())
+            .rev()
+            .filter(|id| !self.slots[*id].alive && !listed.contains(id))
+            .collect();
+
+        for entity in free {
+            let slot = &mut self.slots[entity.id];
+            if slot.alive {
+                error!(
+                    "EntityManager::restore_free_slots() - entity {} is alive",
+                    entity.id
+                );
+                continue;
+            }
+
+            slot.generation = entity.generation;
+            self.free_list.push(entity.id);
+        }
+    }
+
+    /// Adds dead slots until `id` is a valid slot, marking them free.
+    fn grow_to(&mut self, id: usize) {
+        while self.slots.len() <= id {
+            self.free_list.push(self.id_counter);
+            self.id_counter += 1;
+            self.slots.push(EntitySlot {
+                generation: 0,
+                alive: false,
+                location: EntityLocation {
+                    archetype: 0,
+                    row: 0,
+                },
+            });
+        }
+    }
+
+    /// Reserves room for `additional` more entities beyond the slots waiting to be recycled.
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots
+            .reserve(additional.saturating_sub(self.free_list.len()));
+    }
+
+    /// Frees the entity's slot so it can be recycled. Returns `false` if the handle is stale.
+    pub fn destroy(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            error!(
+                "EntityManager::destroy() - entity {} (generation {}) is not alive",
+                entity.id, entity.generation
+            );
+            return false;
+        }
+
+        let slot = &mut self.slots[entity.id];
+        slot.alive = false;
+        slot.generation = slot.generation.wrapping_add(1);
+
+        self.free_list.push(entity.id);
         self.living_entity_count -= 1;
 
         debug!(
             "EntityManager::destroy() - id: {}, living_entity_count: {}",
             entity.id, self.living_entity_count
         );
+
+        true
+    }
+
+    /// Returns where the entity's components are stored, or `None` if the handle is stale.
+    pub fn location(&self, entity: Entity) -> Option<EntityLocation> {
+        if self.is_alive(entity) {
+            Some(self.slots[entity.id].location)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set_location(&mut self, id: usize, location: EntityLocation) {
+        self.slots[id].location = location;
+    }
+
+    /// Iterates over the handles of all living entities, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.alive)
+            .map(|(id, slot)| Entity {
+                id,
+                generation: slot.generation,
+            })
+    }
+
+    /// Returns `true` if the handle refers to a living entity of the current generation.
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        match self.slots.get(entity.id) {
+            Some(slot) => slot.alive && slot.generation == entity.generation,
+            None => false,
+        }
     }
 }
@@ -0,0 +1,355 @@
+use std::{fmt, marker::PhantomData, sync::RwLock};
+
+use crate::Storage;
+
+struct EventInstance<E> {
+    id: usize,
+    event: E,
+}
+
+struct EventBuffers<E> {
+    previous: Vec<EventInstance<E>>, // sent during the previous tick
+    current: Vec<EventInstance<E>>,  // sent during this tick
+    event_count: usize,
+}
+
+/// # Events
+///
+/// A typed, double-buffered event channel. Events are kept for two ticks: everything sent
+/// during one tick can still be read during the next one, after which it is dropped. That way
+/// a reader sees every event no matter whether it runs before or after the sender.
+///
+/// Channels are registered with `World::add_event::<E>()` and live in the storage as a
+/// resource, so `Run` and `Update` implementations, systems and the app can all reach them.
+/// Sending and reading only need `&self`.
+///
+/// Each reader keeps its own `EventCursor`, which remembers the last event it has seen. A
+/// component that keeps its cursor in a field has to move it on every read, so it should reach
+/// the cursor through `Mut::bypass_change_detection()`; otherwise the component is marked
+/// changed for `Changed<T>` on every tick, whether or not there was anything to read.
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::RwLock;
+///
+/// use pixpox_ecs::{
+///     Commands, Component, EventCursor, InputHandler, Mut, Stats, Storage, Update, World,
+/// };
+///
+/// #[derive(Clone)]
+/// struct CellPlaced {
+///     pos: (u32, u32),
+/// }
+///
+/// #[derive(Component)]
+/// #[component(update)]
+/// struct CellCounter {
+///     placed: EventCursor<CellPlaced>,
+///     cell_count: usize,
+/// }
+///
+/// impl Update for CellCounter {
+///     fn update(
+///         mut this: Mut<Self>,
+///         storage: &RwLock<Storage>,
+///         _input: &InputHandler,
+///         _stats: &RwLock<Stats>,
+///         _commands: &Commands,
+///     ) {
+///         let storage = storage.read().unwrap();
+///         let events = storage.events::<CellPlaced>().expect("CellPlaced events were not added");
+///
+///         // reader, moving the cursor does not mark the counter changed
+///         let placed = events.read(&mut this.bypass_change_detection().placed);
+///         if !placed.is_empty() {
+///             this.cell_count += placed.len();
+///         }
+///     }
+/// }
+///
+/// let mut world = World::new();
+/// world.add_event::<CellPlaced>();
+///
+/// // sender
+/// world
+///     .storage
+///     .read()
+///     .unwrap()
+///     .events::<CellPlaced>()
+///     .expect("CellPlaced events were not added")
+///     .send(CellPlaced { pos: (4, 2) });
+/// ```
+pub struct Events<E> {
+    buffers: RwLock<EventBuffers<E>>,
+}
+
+impl<E> Events<E> {
+    pub fn new() -> Self {
+        Self {
+            buffers: RwLock::new(EventBuffers {
+                previous: Vec::new(),
+                current: Vec::new(),
+                event_count: 0,
+            }),
+        }
+    }
+
+    pub fn send(&self, event: E) {
+        let mut buffers = self.buffers.write().expect("Could not write lock events");
+
+        let id = buffers.event_count;
+        buffers.event_count += 1;
+        buffers.current.push(EventInstance { id, event });
+    }
+
+    pub fn send_batch(&self, events: impl IntoIterator<Item = E>) {
+        let mut buffers = self.buffers.write().expect("Could not write lock events");
+
+        for event in events {
+            let id = buffers.event_count;
+            buffers.event_count += 1;
+            buffers.current.push(EventInstance { id, event });
+        }
+    }
+
+    /// Returns every event the cursor has not seen yet, oldest first, and moves the cursor past
+    /// them.
+    pub fn read(&self, cursor: &mut EventCursor<E>) -> Vec<E>
+    where
+        E: Clone,
+    {
+        let buffers = self.buffers.read().expect("Could not read lock events");
+
+        let unread = buffers
+            .previous
+            .iter()
+            .chain(buffers.current.iter())
+            .filter(|instance| instance.id >= cursor.next_id)
+            .map(|instance| instance.event.clone())
+            .collect();
+
+        cursor.next_id = buffers.event_count;
+
+        unread
+    }
+
+    /// Number of events currently buffered, i.e. sent during this tick or the previous one.
+    pub fn len(&self) -> usize {
+        let buffers = self.buffers.read().expect("Could not read lock events");
+        buffers.previous.len() + buffers.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the events sent during the previous tick. Called once per tick by `World::run()`.
+    pub fn update(&mut self) {
+        let buffers = self.buffers.get_mut().expect("Could not lock events");
+
+        std::mem::swap(&mut buffers.previous, &mut buffers.current);
+        buffers.current.clear();
+    }
+
+    /// Drops every buffered event.
+    pub fn clear(&mut self) {
+        let buffers = self.buffers.get_mut().expect("Could not lock events");
+
+        buffers.previous.clear();
+        buffers.current.clear();
+    }
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Remembers which events of type `E` a reader has already seen.
+pub struct EventCursor<E> {
+    next_id: usize,
+    marker: PhantomData<fn() -> E>,
+}
+
+impl<E> EventCursor<E> {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E> Default for EventCursor<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Clone for EventCursor<E> {
+    fn clone(&self) -> Self {
+        Self {
+            next_id: self.next_id,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E> fmt::Debug for EventCursor<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventCursor")
+            .field("next_id", &self.next_id)
+            .finish()
+    }
+}
+
+/// Swaps the buffers of the `Events<E>` resource, if there is one.
+pub(crate) fn update_events<E: 'static + Send + Sync>(storage: &mut Storage) {
+    if let Some(events) = storage.resource_mut::<Events<E>>() {
+        events.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use pixpox_utils::{InputHandler, Stats};
+
+    use super::*;
+    use crate::{
+        query::Changed,
+        testing::{self, NoPixelMap},
+        Commands, Component, Mut, Stage, System, Update,
+    };
+
+    #[derive(Component)]
+    #[component(update)]
+    struct Reader {
+        cursor: EventCursor<u32>,
+        read: usize,
+    }
+
+    impl Update for Reader {
+        fn update(
+            mut this: Mut<Self>,
+            storage: &RwLock<Storage>,
+            _input: &InputHandler,
+            _stats: &RwLock<Stats>,
+            _commands: &Commands,
+        ) {
+            let storage = storage.read().expect("Could not read lock storage");
+            let events = storage.events::<u32>().expect("u32 events were not added");
+
+            let read = events.read(&mut this.bypass_change_detection().cursor);
+            if !read.is_empty() {
+                this.read += read.len();
+            }
+        }
+    }
+
+    #[test]
+    fn events_are_kept_for_two_ticks() {
+        let mut events = Events::new();
+        events.send(1);
+
+        // Readable in the tick it was sent
+        assert_eq!(events.read(&mut EventCursor::new()), vec![1]);
+
+        // and in the next one
+        events.update();
+        events.send(2);
+        assert_eq!(events.read(&mut EventCursor::new()), vec![1, 2]);
+
+        // but gone on the third
+        events.update();
+        assert_eq!(events.read(&mut EventCursor::new()), vec![2]);
+        events.update();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn every_cursor_sees_each_event_once() {
+        let mut events = Events::new();
+        let mut first = EventCursor::new();
+        let mut second = EventCursor::new();
+
+        events.send_batch([1, 2]);
+        assert_eq!(events.read(&mut first), vec![1, 2]);
+
+        events.send(3);
+        assert_eq!(events.read(&mut second), vec![1, 2, 3]);
+        assert_eq!(events.read(&mut first), vec![3]);
+
+        events.update();
+        events.send(4);
+        assert_eq!(events.read(&mut first), vec![4]);
+        assert_eq!(events.read(&mut second), vec![4]);
+        assert!(events.read(&mut first).is_empty());
+        assert!(events.read(&mut second).is_empty());
+    }
+
+    #[test]
+    fn world_run_drops_events_after_two_ticks() {
+        let mut world = testing::world();
+        world.add_event::<u32>();
+        world.add_system(
+            System::new("send", |ctx| {
+                ctx.storage()
+                    .events::<u32>()
+                    .expect("u32 events were not added")
+                    .send(7);
+            })
+            .in_stage(Stage::Startup),
+        );
+
+        let buffered = |world: &crate::World| {
+            world
+                .storage
+                .read()
+                .expect("Could not read lock storage")
+                .events::<u32>()
+                .expect("u32 events were not added")
+                .len()
+        };
+
+        world.run::<NoPixelMap>();
+        assert_eq!(buffered(&world), 1);
+        world.run::<NoPixelMap>();
+        assert_eq!(buffered(&world), 1);
+        world.run::<NoPixelMap>();
+        assert_eq!(buffered(&world), 0);
+    }
+
+    #[test]
+    fn reading_through_bypass_does_not_mark_changed() {
+        let mut world = testing::world();
+        world.add_event::<u32>();
+        let reader = world.spawn_with((Reader {
+            cursor: EventCursor::new(),
+            read: 0,
+        },));
+
+        let changed = Arc::new(Mutex::new(Vec::new()));
+        let system_changed = Arc::clone(&changed);
+        world.add_system(
+            System::new("watch", move |ctx| {
+                let count = ctx.query_filtered::<&Reader, Changed<Reader>>().len();
+                system_changed.lock().unwrap().push(count);
+            })
+            .reads::<Reader>()
+            .in_stage(Stage::PostUpdate),
+        );
+
+        world.run::<NoPixelMap>();
+        world.send_event(1_u32);
+        world.run::<NoPixelMap>();
+        world.run::<NoPixelMap>();
+
+        assert_eq!(world.get_component::<Reader>(reader).unwrap().read, 1);
+        // Added on the first tick, changed only on the tick that read an event
+        assert_eq!(*changed.lock().unwrap(), vec![1, 1, 0]);
+    }
+}
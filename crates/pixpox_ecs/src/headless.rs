@@ -0,0 +1,160 @@
+use std::{collections::VecDeque, io, marker::PhantomData, path::Path, time::Instant};
+
+use log::debug;
+pub use pixpox_utils::SyntheticInput;
+
+use crate::{GlobalPixelMap as GlobalPixelMapTrait, Storage, StorageError, World};
+
+/// # HeadlessRunner
+///
+/// Steps a `World` without a window, GPU or ImGui, for integration tests and offline batch
+/// experiments. Input is scripted with `SyntheticInput`, and the pixel map can be rendered into
+/// a plain RGBA buffer.
+///
+/// `T` is the global pixel map type, which must already be in the world's storage, exactly as
+/// for `World::run::<T>()`. Every step runs one tick directly, ignoring the world's clock.
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut runner = HeadlessRunner::<GlobalPixelMap>::new(world);
+///
+/// // unpause the simulation, then let it run
+/// runner.queue_input(SyntheticInput::new().press_key(VirtualKeyCode::P));
+/// runner.run(100);
+///
+/// let frame = runner.render().expect("No pixel map");
+/// ```
+///
+/// To see where the ticks go, capture a trace around them and open it in Perfetto:
+///
+/// ```ignore
+/// runner.start_trace();
+/// runner.run(100);
+/// runner.save_trace("trace.json").expect("Could not save trace");
+/// ```
+pub struct HeadlessRunner<T> {
+    world: World,
+    inputs: VecDeque<SyntheticInput>, // one entry per upcoming tick
+    ticks: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static + GlobalPixelMapTrait> HeadlessRunner<T> {
+    pub fn new(world: World) -> Self {
+        Self {
+            world,
+            inputs: VecDeque::new(),
+            ticks: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Number of ticks run by this runner.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Feeds `input` to the next tick that has no input queued yet. Ticks without queued input
+    /// see no new presses or releases, but keys and buttons stay held.
+    pub fn queue_input(&mut self, input: SyntheticInput) -> &mut Self {
+        self.inputs.push_back(input);
+        self
+    }
+
+    /// Runs a single tick.
+    pub fn step(&mut self) -> &mut Self {
+        if let Some(input) = self.inputs.pop_front() {
+            self.world.input.queue(&input);
+        }
+
+        self.world.run::<T>();
+        self.world.input.clear_actions();
+        self.ticks += 1;
+
+        self
+    }
+
+    /// Runs `ticks` ticks.
+    pub fn run(&mut self, ticks: u64) -> &mut Self {
+        let now = Instant::now();
+
+        for _ in 0..ticks {
+            self.step();
+        }
+
+        debug!(
+            "HeadlessRunner::run() - {} ticks in {} micros",
+            ticks,
+            now.elapsed().as_micros().to_string()
+        );
+
+        self
+    }
+
+    /// Runs ticks until `predicate` holds, checking it after every tick, but no more than
+    /// `max_ticks` of them. Returns the number of ticks it took, or `None` if it never held.
+    pub fn run_until(
+        &mut self,
+        max_ticks: u64,
+        mut predicate: impl FnMut(&World) -> bool,
+    ) -> Option<u64> {
+        for tick in 1..=max_ticks {
+            self.step();
+
+            if predicate(&self.world) {
+                return Some(tick);
+            }
+        }
+
+        None
+    }
+
+    /// Starts capturing a trace of every tick run from now on, see `Tracer`.
+    pub fn start_trace(&mut self) -> &mut Self {
+        self.world.tracer.start();
+        self
+    }
+
+    /// Stops the trace capture and writes it to `path` as Chrome `trace_event` JSON.
+    pub fn save_trace(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.world.tracer.stop();
+        self.world.tracer.save(path)
+    }
+
+    /// Renders the global pixel map into a new RGBA buffer of the size it reports.
+    pub fn render(&self) -> Result<Vec<u8>, StorageError> {
+        let storage = self
+            .world
+            .storage
+            .read()
+            .expect("Could not read lock storage");
+        let pixelmap = storage.global_pixel_map::<T>()?;
+
+        let (width, height) = pixelmap.size();
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        pixelmap.render(&mut pixels);
+
+        Ok(pixels)
+    }
+
+    pub fn into_world(self) -> World {
+        self.world
+    }
+
+    /// Consumes the runner and returns the final contents of the world's storage.
+    pub fn into_storage(self) -> Storage {
+        self.world
+            .storage
+            .into_inner()
+            .expect("Could not lock storage")
+    }
+}
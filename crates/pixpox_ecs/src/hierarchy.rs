@@ -0,0 +1,32 @@
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{entity::Entity, Component};
+
+/// The entity this entity is attached to.
+///
+/// `Parent` and `Children` are maintained by the world: use `World::set_parent()`,
+/// `World::remove_parent()` and `World::despawn_recursive()`, or the matching `Commands`,
+/// rather than adding or removing them by hand, which leaves the other side of the
+/// relationship out of date.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent(pub(crate) Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The entities attached to this entity, in the order they were attached. See `Parent`.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Children(pub(crate) Vec<Entity>);
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
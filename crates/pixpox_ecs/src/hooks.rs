@@ -0,0 +1,183 @@
+use crate::{archetypes::Column, entity::Entity, Commands, Component, Storage};
+
+/// # ComponentHooks
+///
+/// Optional callbacks the world makes when a component type is added to or taken off an
+/// entity, for components that own something outside of themselves, e.g. an area of the
+/// global pixel map. Hooks are enabled per type with `World::register_hooks::<T>()`.
+///
+/// Every hook gets the entity, the storage and the world's command queue. Structural changes
+/// must be queued on `commands`; they are applied at the next sync point, like those queued
+/// by `Run` and `Update`.
+///
+/// ## Example
+///
+/// ```ignore
+/// impl ComponentHooks for ConwayGridComponent {
+///     fn on_remove(&mut self, _entity: Entity, storage: &mut Storage, _commands: &Commands) {
+///         let mut pixelmap = storage
+///             .write_storage::<GlobalPixelMap>("pixelmap")
+///             .expect("Could not query Pixel Map");
+///
+///         pixelmap.clear_area(self.area());
+///     }
+/// }
+///
+/// world.register_hooks::<ConwayGridComponent>();
+/// ```
+pub trait ComponentHooks: Component {
+    /// Called after the component is added to `entity`, including when it replaces one.
+    fn on_add(&mut self, _entity: Entity, _storage: &mut Storage, _commands: &Commands) {}
+
+    /// Called on the old component right before a new one of the same type replaces it.
+    fn on_replace(&mut self, _entity: Entity, _storage: &mut Storage, _commands: &Commands) {}
+
+    /// Called right before the component is removed from `entity`.
+    fn on_remove(&mut self, _entity: Entity, _storage: &mut Storage, _commands: &Commands) {}
+
+    /// Called right before `entity` is despawned. Calls `on_remove()` unless overridden.
+    fn on_despawn(&mut self, entity: Entity, storage: &mut Storage, commands: &Commands) {
+        self.on_remove(entity, storage, commands);
+    }
+}
+
+pub(crate) type HookFn = fn(&mut dyn Column, usize, Entity, &mut Storage, &Commands);
+
+/// The hooks of a component type, type-erased so they can be called on a column.
+#[derive(Clone, Copy)]
+pub(crate) struct Hooks {
+    pub on_add: HookFn,
+    pub on_replace: HookFn,
+    pub on_remove: HookFn,
+    pub on_despawn: HookFn,
+}
+
+impl Hooks {
+    pub fn new<T: ComponentHooks>() -> Self {
+        Self {
+            on_add: |column, row, entity, storage, commands| {
+                component_at::<T>(column, row).on_add(entity, storage, commands)
+            },
+            on_replace: |column, row, entity, storage, commands| {
+                component_at::<T>(column, row).on_replace(entity, storage, commands)
+            },
+            on_remove: |column, row, entity, storage, commands| {
+                component_at::<T>(column, row).on_remove(entity, storage, commands)
+            },
+            on_despawn: |column, row, entity, storage, commands| {
+                component_at::<T>(column, row).on_despawn(entity, storage, commands)
+            },
+        }
+    }
+}
+
+fn component_at<T: 'static>(column: &mut dyn Column, row: usize) -> &mut T {
+    &mut column
+        .as_any_mut()
+        .downcast_mut::<Vec<T>>()
+        .expect("Column type does not match its hooks")[row]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{self, Name, NoPixelMap},
+        World,
+    };
+
+    #[derive(Default)]
+    struct HookLog(Vec<(&'static str, u32)>);
+
+    #[derive(Component, Debug, PartialEq)]
+    struct Tracked(u32);
+
+    impl Tracked {
+        fn log(&self, hook: &'static str, storage: &mut Storage) {
+            storage
+                .resource_mut::<HookLog>()
+                .expect("No hook log")
+                .0
+                .push((hook, self.0));
+        }
+    }
+
+    impl ComponentHooks for Tracked {
+        fn on_add(&mut self, _entity: Entity, storage: &mut Storage, commands: &Commands) {
+            self.log("add", storage);
+            commands.spawn().with(Name(format!("added {}", self.0)));
+        }
+
+        fn on_replace(&mut self, _entity: Entity, storage: &mut Storage, _commands: &Commands) {
+            self.log("replace", storage);
+        }
+
+        fn on_remove(&mut self, _entity: Entity, storage: &mut Storage, _commands: &Commands) {
+            self.log("remove", storage);
+        }
+
+        fn on_despawn(&mut self, _entity: Entity, storage: &mut Storage, _commands: &Commands) {
+            self.log("despawn", storage);
+        }
+    }
+
+    fn world() -> World {
+        let mut world = testing::world();
+        world
+            .storage
+            .get_mut()
+            .expect("Could not lock storage")
+            .insert_resource(HookLog::default());
+        world.register_hooks::<Tracked>();
+        world
+    }
+
+    fn take_log(world: &mut World) -> Vec<(&'static str, u32)> {
+        let storage = world.storage.get_mut().expect("Could not lock storage");
+        std::mem::take(&mut storage.resource_mut::<HookLog>().expect("No hook log").0)
+    }
+
+    #[test]
+    fn each_hook_fires_once() {
+        let mut world = world();
+        let entity = world.spawn();
+
+        world.add_component_to_entity(entity, Tracked(1));
+        assert_eq!(take_log(&mut world), vec![("add", 1)]);
+
+        world.add_component_to_entity(entity, Tracked(2));
+        assert_eq!(take_log(&mut world), vec![("replace", 1), ("add", 2)]);
+
+        assert_eq!(world.remove_component::<Tracked>(entity), Some(Tracked(2)));
+        assert_eq!(take_log(&mut world), vec![("remove", 2)]);
+        assert_eq!(world.remove_component::<Tracked>(entity), None);
+        assert!(take_log(&mut world).is_empty());
+
+        world.add_component_to_entity(entity, Tracked(3));
+        world.despawn(entity);
+        assert_eq!(take_log(&mut world), vec![("add", 3), ("despawn", 3)]);
+        world.despawn(entity);
+        assert!(take_log(&mut world).is_empty());
+
+        world.spawn_batch([(Tracked(4),), (Tracked(5),)]);
+        assert_eq!(take_log(&mut world), vec![("add", 4), ("add", 5)]);
+    }
+
+    #[test]
+    fn commands_queued_by_hooks_are_applied() {
+        let mut world = world();
+        world.spawn_with((Tracked(1),));
+        world.spawn_with((Tracked(2),));
+        assert!(world.query::<&Name>().is_empty());
+
+        world.run::<NoPixelMap>();
+
+        let mut names: Vec<String> = world
+            .query::<&Name>()
+            .into_iter()
+            .map(|(_, name)| name.0.clone())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["added 1", "added 2"]);
+    }
+}
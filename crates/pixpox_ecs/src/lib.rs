@@ -1,9 +1,42 @@
+// Lets code generated by `pixpox_ecs_macros` refer to `::pixpox_ecs` inside this crate too.
+extern crate self as pixpox_ecs;
+
 pub mod entity;
+pub mod change_detection;
+pub mod archetypes;
+pub mod bundle;
 
 pub mod world;
 pub mod component;
 pub mod storage;
+pub mod query;
+pub mod command;
+pub mod event;
+pub mod headless;
+pub mod hierarchy;
+pub mod hooks;
+pub mod snapshot;
+pub mod system;
+pub mod timestep;
+pub mod transform;
+
+#[cfg(test)]
+mod testing;
 
 pub use world::*;
 pub use component::*;
 pub use storage::*;
+pub use query::*;
+pub use bundle::Bundle;
+pub use pixpox_ecs_macros::{Bundle, Component};
+pub use pixpox_utils::{Stats, Tracer};
+pub use change_detection::{ComponentTicks, Mut};
+pub use command::{Commands, SpawnCommand};
+pub use event::{EventCursor, Events};
+pub use headless::{HeadlessRunner, SyntheticInput};
+pub use hierarchy::{Children, Parent};
+pub use hooks::ComponentHooks;
+pub use snapshot::{SnapshotError, SnapshotFormat};
+pub use system::{Schedule, Stage, System, SystemContext};
+pub use timestep::FixedTimestep;
+pub use transform::{propagate_transforms, Affine2, GlobalTransform2D, Transform2D};
@@ -0,0 +1,771 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use crate::{
+    archetypes::Archetype,
+    change_detection::{ComponentTicks, Mut, Ticks},
+    entity::Entity,
+};
+
+/// Records which component types and `Storage` buckets a query or system reads and which it
+/// writes.
+///
+/// A query may read a component type any number of times, but a written type may not appear
+/// anywhere else in the same query, otherwise it would hand out aliasing `&mut` references.
+/// Two systems whose accesses are not compatible are never run at the same time.
+#[derive(Debug, Default, Clone)]
+pub struct Access {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+    bucket_reads: Vec<&'static str>,
+    bucket_writes: Vec<&'static str>,
+    resource_reads: Vec<(TypeId, &'static str)>,
+    resource_writes: Vec<(TypeId, &'static str)>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_read<T: 'static>(&mut self) {
+        self.reads
+            .push((TypeId::of::<T>(), std::any::type_name::<T>()));
+    }
+
+    pub fn add_write<T: 'static>(&mut self) {
+        self.writes
+            .push((TypeId::of::<T>(), std::any::type_name::<T>()));
+    }
+
+    pub fn add_bucket_read(&mut self, label: &'static str) {
+        self.bucket_reads.push(label);
+    }
+
+    pub fn add_bucket_write(&mut self, label: &'static str) {
+        self.bucket_writes.push(label);
+    }
+
+    pub fn add_resource_read<T: 'static>(&mut self) {
+        self.resource_reads
+            .push((TypeId::of::<T>(), std::any::type_name::<T>()));
+    }
+
+    pub fn add_resource_write<T: 'static>(&mut self) {
+        self.resource_writes
+            .push((TypeId::of::<T>(), std::any::type_name::<T>()));
+    }
+
+    pub fn bucket_reads(&self) -> &[&'static str] {
+        &self.bucket_reads
+    }
+
+    pub fn bucket_writes(&self) -> &[&'static str] {
+        &self.bucket_writes
+    }
+
+    pub fn resource_reads(&self) -> &[(TypeId, &'static str)] {
+        &self.resource_reads
+    }
+
+    pub fn resource_writes(&self) -> &[(TypeId, &'static str)] {
+        &self.resource_writes
+    }
+
+    /// Returns the name of the first type that is written while also being read or written
+    /// elsewhere in the same access set.
+    pub fn find_conflict(&self) -> Option<&'static str> {
+        for (i, (write, name)) in self.writes.iter().enumerate() {
+            let written_twice = self.writes[i + 1..].iter().any(|(other, _)| other == write);
+            let also_read = self.reads.iter().any(|(other, _)| other == write);
+
+            if written_twice || also_read {
+                return Some(name);
+            }
+        }
+
+        None
+    }
+
+    /// Returns `true` if both access sets can be used at the same time.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        fn overlaps<K: PartialEq, V>(a: &[(K, V)], b: &[(K, V)]) -> bool {
+            a.iter().any(|(x, _)| b.iter().any(|(y, _)| x == y))
+        }
+
+        fn labels_overlap(a: &[&'static str], b: &[&'static str]) -> bool {
+            a.iter().any(|x| b.contains(x))
+        }
+
+        !overlaps(&self.writes, &other.writes)
+            && !overlaps(&self.writes, &other.reads)
+            && !overlaps(&self.reads, &other.writes)
+            && !labels_overlap(&self.bucket_writes, &other.bucket_writes)
+            && !labels_overlap(&self.bucket_writes, &other.bucket_reads)
+            && !labels_overlap(&self.bucket_reads, &other.bucket_writes)
+            && !overlaps(&self.resource_writes, &other.resource_writes)
+            && !overlaps(&self.resource_writes, &other.resource_reads)
+            && !overlaps(&self.resource_reads, &other.resource_writes)
+    }
+
+    /// Returns the name of the first component type this access uses that `declared` does not
+    /// allow, i.e. a read that is not declared at all or a write that is not declared as such.
+    pub fn find_undeclared(&self, declared: &Access) -> Option<&'static str> {
+        let declares = |list: &[(TypeId, &'static str)], type_id: &TypeId| {
+            list.iter().any(|(other, _)| other == type_id)
+        };
+
+        for (type_id, name) in self.reads.iter() {
+            if !declares(&declared.reads, type_id) && !declares(&declared.writes, type_id) {
+                return Some(name);
+            }
+        }
+
+        for (type_id, name) in self.writes.iter() {
+            if !declares(&declared.writes, type_id) {
+                return Some(name);
+            }
+        }
+
+        None
+    }
+}
+
+/// A raw pointer to the dense `Vec<T>` column of one archetype.
+///
+/// Queries check their `Access` before handing these out, and every row is fetched at most
+/// once per query, so sending the pointer to other threads never produces aliasing references.
+pub struct ColumnPtr<T> {
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<T> Clone for ColumnPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ColumnPtr<T> {}
+
+unsafe impl<T: Send + Sync> Send for ColumnPtr<T> {}
+unsafe impl<T: Send + Sync> Sync for ColumnPtr<T> {}
+
+impl<T: 'static> ColumnPtr<T> {
+    /// Pointer for reading only.
+    fn find(archetype: &Archetype) -> Option<Self> {
+        archetype.components::<T>().map(|vec| ColumnPtr {
+            ptr: vec.as_ptr() as *mut T,
+            len: vec.len(),
+        })
+    }
+
+    /// # Safety
+    ///
+    /// No other reference to the column may exist while the pointer is in use.
+    unsafe fn find_mut(archetype: &Archetype) -> Option<Self> {
+        archetype
+            .column_unchecked_mut(TypeId::of::<T>())
+            .and_then(|column| column.as_any_mut().downcast_mut::<Vec<T>>())
+            .map(|vec| ColumnPtr {
+                ptr: vec.as_mut_ptr(),
+                len: vec.len(),
+            })
+    }
+
+    /// # Safety
+    ///
+    /// The column must not have been reallocated since the pointer was taken.
+    unsafe fn get<'w>(self, row: usize) -> &'w T {
+        debug_assert!(row < self.len);
+        &*self.ptr.add(row)
+    }
+
+    /// # Safety
+    ///
+    /// Same as `get()`, and no other reference to the row may exist.
+    unsafe fn get_mut<'w>(self, row: usize) -> &'w mut T {
+        debug_assert!(row < self.len);
+        &mut *self.ptr.add(row)
+    }
+}
+
+impl ColumnPtr<ComponentTicks> {
+    /// Pointer for reading the change ticks of the column holding `type_id`.
+    fn ticks(archetype: &Archetype, type_id: TypeId) -> Option<Self> {
+        archetype.ticks(type_id).map(|ticks| ColumnPtr {
+            ptr: ticks.as_ptr() as *mut ComponentTicks,
+            len: ticks.len(),
+        })
+    }
+
+    /// # Safety
+    ///
+    /// Same as `find_mut()`, for the column's change ticks.
+    unsafe fn ticks_mut(archetype: &Archetype, type_id: TypeId) -> Option<Self> {
+        archetype
+            .ticks_unchecked_mut(type_id)
+            .map(|ticks| ColumnPtr {
+                ptr: ticks.as_mut_ptr(),
+                len: ticks.len(),
+            })
+    }
+}
+
+/// A single element of a query, either `&T` or `&mut T`. `&mut T` is fetched as `Mut<T>`,
+/// which marks the component changed when it is written to.
+///
+/// # Safety
+///
+/// `access()` must report every component type that `fetch()` reads or writes.
+pub unsafe trait Fetch {
+    type Item<'w>: Send;
+    type Column: Copy + Send + Sync + 'static;
+
+    fn access(access: &mut Access);
+
+    /// Returns the column to fetch from, or `None` if the archetype does not match.
+    ///
+    /// # Safety
+    ///
+    /// Columns this fetch writes to must not be borrowed anywhere else.
+    unsafe fn column(archetype: &Archetype) -> Option<Self::Column>;
+
+    /// # Safety
+    ///
+    /// The column must still be alive and no other reference to the row may exist.
+    unsafe fn fetch<'w>(column: Self::Column, row: usize, ticks: Ticks) -> Self::Item<'w>;
+}
+
+unsafe impl<T: 'static + Send + Sync> Fetch for &T {
+    type Item<'w> = &'w T;
+    type Column = ColumnPtr<T>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn column(archetype: &Archetype) -> Option<Self::Column> {
+        ColumnPtr::find(archetype)
+    }
+
+    unsafe fn fetch<'w>(column: Self::Column, row: usize, _ticks: Ticks) -> Self::Item<'w> {
+        column.get(row)
+    }
+}
+
+unsafe impl<T: 'static + Send + Sync> Fetch for &mut T {
+    type Item<'w> = Mut<'w, T>;
+    type Column = (ColumnPtr<T>, ColumnPtr<ComponentTicks>);
+
+    fn access(access: &mut Access) {
+        access.add_write::<T>();
+    }
+
+    unsafe fn column(archetype: &Archetype) -> Option<Self::Column> {
+        Some((
+            ColumnPtr::find_mut(archetype)?,
+            ColumnPtr::ticks_mut(archetype, TypeId::of::<T>())?,
+        ))
+    }
+
+    unsafe fn fetch<'w>(column: Self::Column, row: usize, ticks: Ticks) -> Self::Item<'w> {
+        let (components, component_ticks) = column;
+        Mut::new(
+            components.get_mut(row),
+            component_ticks.get_mut(row),
+            ticks.this_run,
+        )
+    }
+}
+
+/// Fetches `Some` component for entities that have it and `None` for those that don't, without
+/// narrowing the query down, e.g. `(&Position, Option<&Sprite>)`.
+unsafe impl<F: Fetch> Fetch for Option<F> {
+    type Item<'w> = Option<F::Item<'w>>;
+    type Column = Option<F::Column>;
+
+    fn access(access: &mut Access) {
+        F::access(access);
+    }
+
+    unsafe fn column(archetype: &Archetype) -> Option<Self::Column> {
+        Some(F::column(archetype))
+    }
+
+    unsafe fn fetch<'w>(column: Self::Column, row: usize, ticks: Ticks) -> Self::Item<'w> {
+        column.map(|column| F::fetch(column, row, ticks))
+    }
+}
+
+/// A set of component types fetched together, e.g. `(&Position, &mut Velocity)`.
+///
+/// Each entity whose archetype has all of the types is yielded as a flat tuple
+/// `(Entity, &Position, Mut<Velocity>)`. A single `&T` or `&mut T` is a query too and yields
+/// `(Entity, &T)`.
+///
+/// ### Example
+///
+/// ```ignore
+/// for (entity, health, damage) in world.query::<(&mut Health, &Damage)>() {
+///     health.0 -= damage.0;
+/// }
+///
+/// world
+///     .par_query::<(&mut Position, &Velocity)>()
+///     .for_each(|(_, pos, vel)| pos.apply(vel));
+/// ```
+pub trait Query {
+    type Item<'w>: Send;
+    type Columns: Copy + Send + Sync + 'static;
+
+    fn access(access: &mut Access);
+
+    /// # Safety
+    ///
+    /// See `Fetch::column()`.
+    unsafe fn columns(archetype: &Archetype) -> Option<Self::Columns>;
+
+    /// # Safety
+    ///
+    /// See `Fetch::fetch()`.
+    unsafe fn fetch<'w>(
+        columns: Self::Columns,
+        entity: Entity,
+        row: usize,
+        ticks: Ticks,
+    ) -> Self::Item<'w>;
+}
+
+impl<F: Fetch> Query for F {
+    type Item<'w> = (Entity, F::Item<'w>);
+    type Columns = F::Column;
+
+    fn access(access: &mut Access) {
+        F::access(access);
+    }
+
+    unsafe fn columns(archetype: &Archetype) -> Option<Self::Columns> {
+        F::column(archetype)
+    }
+
+    unsafe fn fetch<'w>(
+        columns: Self::Columns,
+        entity: Entity,
+        row: usize,
+        ticks: Ticks,
+    ) -> Self::Item<'w> {
+        (entity, F::fetch(columns, row, ticks))
+    }
+}
+
+macro_rules! impl_query_for_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Fetch),+> Query for ($($name,)+) {
+            type Item<'w> = (Entity, $($name::Item<'w>,)+);
+            type Columns = ($($name::Column,)+);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn columns(archetype: &Archetype) -> Option<Self::Columns> {
+                Some(($($name::column(archetype)?,)+))
+            }
+
+            unsafe fn fetch<'w>(
+                columns: Self::Columns,
+                entity: Entity,
+                row: usize,
+                ticks: Ticks,
+            ) -> Self::Item<'w> {
+                let ($($name,)+) = columns;
+                (entity, $($name::fetch($name, row, ticks),)+)
+            }
+        }
+    };
+}
+
+impl_query_for_tuple!(A);
+impl_query_for_tuple!(A, B);
+impl_query_for_tuple!(A, B, C);
+impl_query_for_tuple!(A, B, C, D);
+impl_query_for_tuple!(A, B, C, D, E);
+impl_query_for_tuple!(A, B, C, D, E, F);
+impl_query_for_tuple!(A, B, C, D, E, F, G);
+impl_query_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// Narrows a query down to the entities that match it, without fetching anything, e.g.
+/// `world.query_filtered::<&Cell, Changed<Cell>>()`. A tuple of filters matches when all of
+/// them do, `Or<(A, B)>` when any of them does, and `()` matches everything.
+///
+/// ### Example
+///
+/// ```ignore
+/// // cells that are not frozen, and were either just placed or heated up
+/// world.query_filtered::<&mut Cell, (Without<Frozen>, Or<(Added<Cell>, Changed<Heat>)>)>();
+/// ```
+///
+/// # Safety
+///
+/// `access()` must report every component type that `matches()` reads.
+pub unsafe trait Filter {
+    type State: Copy + Send + Sync + 'static;
+
+    fn access(access: &mut Access);
+
+    /// Returns what `matches()` needs, or `None` if no entity of the archetype can match.
+    ///
+    /// # Safety
+    ///
+    /// The archetype must outlive every use of the returned state.
+    unsafe fn state(archetype: &Archetype) -> Option<Self::State>;
+
+    /// # Safety
+    ///
+    /// The state must still be alive and `row` in bounds.
+    unsafe fn matches(state: Self::State, row: usize, ticks: Ticks) -> bool;
+}
+
+unsafe impl Filter for () {
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    unsafe fn state(_archetype: &Archetype) -> Option<Self::State> {
+        Some(())
+    }
+
+    unsafe fn matches(_state: Self::State, _row: usize, _ticks: Ticks) -> bool {
+        true
+    }
+}
+
+/// Matches entities whose `T` was added since the query last looked, i.e. since the system
+/// last ran, or since the start of the latest `World::run()` outside of systems.
+pub struct Added<T>(PhantomData<T>);
+
+/// Matches entities whose `T` was added or written to since the query last looked, see
+/// `Added<T>`.
+pub struct Changed<T>(PhantomData<T>);
+
+unsafe impl<T: 'static> Filter for Added<T> {
+    type State = ColumnPtr<ComponentTicks>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+        ColumnPtr::ticks(archetype, TypeId::of::<T>())
+    }
+
+    unsafe fn matches(state: Self::State, row: usize, ticks: Ticks) -> bool {
+        state.get(row).is_added(ticks)
+    }
+}
+
+unsafe impl<T: 'static> Filter for Changed<T> {
+    type State = ColumnPtr<ComponentTicks>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+        ColumnPtr::ticks(archetype, TypeId::of::<T>())
+    }
+
+    unsafe fn matches(state: Self::State, row: usize, ticks: Ticks) -> bool {
+        state.get(row).is_changed(ticks)
+    }
+}
+
+/// Matches entities that have a `T`, without fetching it.
+pub struct With<T>(PhantomData<T>);
+
+/// Matches entities that have no `T`.
+pub struct Without<T>(PhantomData<T>);
+
+/// Matches entities that match any of the filters in the tuple, e.g.
+/// `Or<(With<Fire>, With<Lava>)>`.
+pub struct Or<T>(PhantomData<T>);
+
+unsafe impl<T: 'static> Filter for With<T> {
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+        archetype.contains(TypeId::of::<T>()).then_some(())
+    }
+
+    unsafe fn matches(_state: Self::State, _row: usize, _ticks: Ticks) -> bool {
+        true
+    }
+}
+
+unsafe impl<T: 'static> Filter for Without<T> {
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+        (!archetype.contains(TypeId::of::<T>())).then_some(())
+    }
+
+    unsafe fn matches(_state: Self::State, _row: usize, _ticks: Ticks) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_filter_for_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: Filter),+> Filter for ($($name,)+) {
+            type State = ($($name::State,)+);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+                Some(($($name::state(archetype)?,)+))
+            }
+
+            unsafe fn matches(state: Self::State, row: usize, ticks: Ticks) -> bool {
+                let ($($name,)+) = state;
+                $($name::matches($name, row, ticks))&&+
+            }
+        }
+    };
+}
+
+impl_filter_for_tuple!(A);
+impl_filter_for_tuple!(A, B);
+impl_filter_for_tuple!(A, B, C);
+impl_filter_for_tuple!(A, B, C, D);
+
+macro_rules! impl_or_filter {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: Filter),+> Filter for Or<($($name,)+)> {
+            // `None` for the filters that match nothing in the archetype
+            type State = ($(Option<$name::State>,)+);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            unsafe fn state(archetype: &Archetype) -> Option<Self::State> {
+                let state = ($($name::state(archetype),)+);
+
+                let ($($name,)+) = state;
+                if $($name.is_none())&&+ {
+                    return None;
+                }
+
+                Some(state)
+            }
+
+            unsafe fn matches(state: Self::State, row: usize, ticks: Ticks) -> bool {
+                let ($($name,)+) = state;
+                $($name.map_or(false, |state| $name::matches(state, row, ticks)))||+
+            }
+        }
+    };
+}
+
+impl_or_filter!(A, B);
+impl_or_filter!(A, B, C);
+impl_or_filter!(A, B, C, D);
+
+/// Panics if the query would hand out aliasing references.
+pub(crate) fn validate_access<Q: Query>() {
+    let mut access = Access::new();
+    Q::access(&mut access);
+
+    if let Some(name) = access.find_conflict() {
+        panic!(
+            "Query {} accesses {} mutably while also accessing it elsewhere",
+            std::any::type_name::<Q>(),
+            name
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        entity::Entity,
+        testing::{self, Name, NoPixelMap, Position, Velocity},
+        Component, Stage, System, World,
+    };
+
+    fn ids<T>(items: Vec<(Entity, T)>) -> Vec<usize> {
+        let mut ids: Vec<usize> = items.into_iter().map(|(entity, _)| entity.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn with_without_and_or() {
+        let mut world = World::new();
+        let both = world.spawn_with((Position(0, 0), Velocity(0, 0)));
+        let position = world.spawn_with((Position(1, 1),));
+        world.spawn_with((Velocity(2, 2),));
+        let named = world.spawn_with((Position(3, 3), Name("named".to_string())));
+
+        assert_eq!(
+            ids(world.query_filtered::<&Position, With<Velocity>>()),
+            vec![both.id]
+        );
+        assert_eq!(
+            ids(world.query_filtered::<&Position, Without<Velocity>>()),
+            vec![position.id, named.id]
+        );
+        assert_eq!(
+            ids(world.query_filtered::<&Position, Or<(With<Velocity>, With<Name>)>>()),
+            vec![both.id, named.id]
+        );
+        assert_eq!(
+            ids(world.query_filtered::<&Position, (Without<Velocity>, Without<Name>)>()),
+            vec![position.id]
+        );
+    }
+
+    #[test]
+    fn added_and_changed() {
+        let mut world = testing::world();
+
+        // Moves the first entity on the third tick only
+        let runs = Arc::new(AtomicU32::new(0));
+        world.add_system(
+            System::new("move", move |ctx| {
+                if runs.fetch_add(1, Ordering::Relaxed) == 2 {
+                    for (_, mut position) in ctx.query::<&mut Position>() {
+                        if position.1 == 0 {
+                            position.0 += 10;
+                        }
+                    }
+                }
+            })
+            .writes::<Position>()
+            .in_stage(Stage::PreUpdate),
+        );
+
+        // The entities each run of the system saw as added and as changed
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let system_seen = Arc::clone(&seen);
+        world.add_system(
+            System::new("watch", move |ctx| {
+                let added = ids(ctx.query_filtered::<&Position, Added<Position>>());
+                let changed = ids(ctx.query_filtered::<&Position, Changed<Position>>());
+                system_seen.lock().unwrap().push((added, changed));
+            })
+            .reads::<Position>(),
+        );
+
+        let first = world.spawn_with((Position(0, 0),));
+        let second = world.spawn_with((Position(1, 1),));
+        for _ in 0..4 {
+            world.run::<NoPixelMap>();
+        }
+
+        assert_eq!(
+            world.get_component::<Position>(first),
+            Some(&Position(10, 0))
+        );
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (vec![first.id, second.id], vec![first.id, second.id]),
+                (vec![], vec![]),
+                (vec![], vec![first.id]),
+                (vec![], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_between_ticks_are_seen() {
+        let mut world = testing::world();
+
+        // Runs after every component's `run()` and `update()`, which write nothing here
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let system_seen = Arc::clone(&seen);
+        world.add_system(
+            System::new("watch", move |ctx| {
+                let added = ids(ctx.query_filtered::<&Position, Added<Position>>());
+                let changed = ids(ctx.query_filtered::<&Position, Changed<Position>>());
+                system_seen.lock().unwrap().push((added, changed));
+            })
+            .reads::<Position>()
+            .in_stage(Stage::PreUpdate),
+        );
+
+        let first = world.spawn_with((Position(0, 0),));
+        let second = world.spawn_with((Position(1, 1),));
+        world.run::<NoPixelMap>();
+
+        world.get_component_mut::<Position>(first).unwrap().0 = 10;
+        world.run::<NoPixelMap>();
+
+        // Reading through a `Mut` does not count as a change
+        let _ = world.get_component_mut::<Position>(second).unwrap().0;
+        let third = world.spawn_with((Position(2, 2),));
+        world.run::<NoPixelMap>();
+
+        world.run::<NoPixelMap>();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (vec![first.id, second.id], vec![first.id, second.id]),
+                (vec![], vec![first.id]),
+                (vec![third.id], vec![third.id]),
+                (vec![], vec![]),
+            ]
+        );
+    }
+
+    #[derive(Component)]
+    #[component(label = "Counter", run)]
+    struct Counter(u32);
+
+    impl crate::Run for Counter {
+        fn run(mut this: crate::Mut<Self>, _storage: &crate::Storage, _commands: &crate::Commands) {
+            this.0 += 1;
+        }
+    }
+
+    #[test]
+    fn component_writes_are_seen_by_systems_that_ran_before_them() {
+        let mut world = testing::world();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let system_seen = Arc::clone(&seen);
+        world.add_system(
+            System::new("watch", move |ctx| {
+                let changed = ids(ctx.query_filtered::<&Counter, Changed<Counter>>());
+                system_seen.lock().unwrap().push(changed);
+            })
+            .reads::<Counter>()
+            .in_stage(Stage::PreUpdate),
+        );
+
+        let counter = world.spawn_with((Counter(0),));
+        for _ in 0..3 {
+            world.run::<NoPixelMap>();
+        }
+
+        assert_eq!(world.get_component::<Counter>(counter).unwrap().0, 3);
+        assert_eq!(*seen.lock().unwrap(), vec![vec![counter.id]; 3]);
+    }
+}
@@ -0,0 +1,677 @@
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+};
+
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{archetypes::Archetype, entity::Entity, Component, Storage, StorageError, World};
+
+/// Version written into every snapshot. Bump it whenever the layout below changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// First bytes of a binary snapshot, followed by the version as a little-endian `u32`.
+const MAGIC: &[u8; 4] = b"PXPX";
+
+/// How a snapshot is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Compact bincode, for saving and loading quickly.
+    Binary,
+    /// Pretty-printed JSON, for reading, diffing and editing by hand.
+    Json,
+}
+
+impl SnapshotFormat {
+    /// Tells the format apart by the binary magic bytes.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(MAGIC) {
+            SnapshotFormat::Binary
+        } else {
+            SnapshotFormat::Json
+        }
+    }
+}
+
+/// Every way saving or loading a snapshot can fail.
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Could not read or write snapshot: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not encode or decode binary snapshot: {0}")]
+    Binary(#[from] bincode::Error),
+    #[error("Could not encode or decode JSON snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Not a PixPox snapshot")]
+    InvalidHeader,
+    #[error("Snapshot version {found} is not supported, expected version {expected}")]
+    UnsupportedVersion { found: u32, expected: u32 },
+    /// The snapshot holds a component no type was registered for with
+    /// `World::register_serializable()`.
+    #[error("Component `{0}` is not registered as serializable")]
+    UnknownComponent(String),
+    /// The snapshot holds a bucket no type was registered for with
+    /// `World::register_serializable_bucket()`.
+    #[error("Bucket `{0}` is not registered as serializable")]
+    UnknownBucket(String),
+    #[error("Entity {0} appears more than once in the snapshot")]
+    DuplicateEntity(usize),
+    /// Snapshots restore entities under their original ids, which may already be taken.
+    #[error("Snapshots can only be loaded into a world without living entities")]
+    WorldNotEmpty,
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// What a single component or bucket is encoded into before it is written out as part of a
+/// snapshot: its own bincode bytes in binary snapshots, a JSON value in JSON snapshots.
+trait Payload: Sized + Serialize + DeserializeOwned {
+    fn encode<T: Serialize>(value: &T) -> Result<Self, SnapshotError>;
+
+    fn decode<T: DeserializeOwned>(self) -> Result<T, SnapshotError>;
+
+    fn component_codec(component: &SerializableComponent) -> &ComponentCodec<Self>;
+
+    fn bucket_codec(bucket: &SerializableBucket) -> &BucketCodec<Self>;
+}
+
+impl Payload for Vec<u8> {
+    fn encode<T: Serialize>(value: &T) -> Result<Self, SnapshotError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(self) -> Result<T, SnapshotError> {
+        Ok(bincode::deserialize(&self)?)
+    }
+
+    fn component_codec(component: &SerializableComponent) -> &ComponentCodec<Self> {
+        &component.binary
+    }
+
+    fn bucket_codec(bucket: &SerializableBucket) -> &BucketCodec<Self> {
+        &bucket.binary
+    }
+}
+
+impl Payload for serde_json::Value {
+    fn encode<T: Serialize>(value: &T) -> Result<Self, SnapshotError> {
+        Ok(serde_json::to_value(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(self) -> Result<T, SnapshotError> {
+        Ok(serde_json::from_value(self)?)
+    }
+
+    fn component_codec(component: &SerializableComponent) -> &ComponentCodec<Self> {
+        &component.json
+    }
+
+    fn bucket_codec(bucket: &SerializableBucket) -> &BucketCodec<Self> {
+        &bucket.json
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<P> {
+    version: u32,
+    entities: Vec<EntitySnapshot<P>>,
+    free_entities: Vec<Entity>, // despawned slots, so respawning hands out the same handles
+    buckets: BTreeMap<String, P>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntitySnapshot<P> {
+    id: usize,
+    generation: u32,
+    components: BTreeMap<String, P>,
+}
+
+/// Lets a JSON snapshot's version be checked before the rest of it is decoded.
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+/// A decoded component, waiting to be added to its entity once the whole snapshot decoded.
+type ComponentInsert = Box<dyn FnOnce(&mut World, Entity)>;
+
+/// A decoded bucket, waiting to be put into the storage once the whole snapshot decoded.
+type BucketInsert = Box<dyn FnOnce(&mut Storage, &'static str)>;
+
+struct ComponentCodec<P> {
+    save: fn(&Archetype, usize) -> Result<P, SnapshotError>,
+    decode: fn(P) -> Result<ComponentInsert, SnapshotError>,
+}
+
+struct BucketCodec<P> {
+    save: fn(&Storage, &'static str) -> Result<P, SnapshotError>,
+    decode: fn(P) -> Result<BucketInsert, SnapshotError>,
+}
+
+struct SerializableComponent {
+    name: &'static str,
+    type_id: TypeId,
+    binary: ComponentCodec<Vec<u8>>,
+    json: ComponentCodec<serde_json::Value>,
+}
+
+struct SerializableBucket {
+    label: &'static str,
+    binary: BucketCodec<Vec<u8>>,
+    json: BucketCodec<serde_json::Value>,
+}
+
+/// The component types and buckets that are written into snapshots, and how to encode them.
+///
+/// Components are stored under the name they were registered with rather than their type, so
+/// a snapshot can be loaded by any build that registers the same names.
+///
+/// ## Example
+///
+/// ```ignore
+/// fn register(world: &mut World) {
+///     world.register_serializable::<Cell>("Cell");
+///     world.register_serializable_bucket::<ConwayGrid>("grid");
+/// }
+///
+/// register(&mut world);
+/// world.save_snapshot("glider.json", SnapshotFormat::Json)?;
+///
+/// let mut restored = World::new();
+/// register(&mut restored);
+/// restored.load_snapshot("glider.json")?;
+/// ```
+#[derive(Default)]
+pub struct SnapshotRegistry {
+    components: Vec<SerializableComponent>,
+    buckets: Vec<SerializableBucket>,
+}
+
+impl SnapshotRegistry {
+    pub(crate) fn register_component<T: Component + Serialize + DeserializeOwned>(
+        &mut self,
+        name: &'static str,
+    ) {
+        let type_id = TypeId::of::<T>();
+
+        if self
+            .components
+            .iter()
+            .any(|component| component.name == name || component.type_id == type_id)
+        {
+            warn!(
+                "SnapshotRegistry::register_component() - {} is already registered",
+                name
+            );
+            return;
+        }
+
+        self.components.push(SerializableComponent {
+            name,
+            type_id,
+            binary: ComponentCodec {
+                save: save_component::<T, Vec<u8>>,
+                decode: decode_component::<T, Vec<u8>>,
+            },
+            json: ComponentCodec {
+                save: save_component::<T, serde_json::Value>,
+                decode: decode_component::<T, serde_json::Value>,
+            },
+        });
+    }
+
+    pub(crate) fn register_bucket<T: 'static + Send + Sync + Serialize + DeserializeOwned>(
+        &mut self,
+        label: &'static str,
+    ) {
+        if self.buckets.iter().any(|bucket| bucket.label == label) {
+            warn!(
+                "SnapshotRegistry::register_bucket() - {} is already registered",
+                label
+            );
+            return;
+        }
+
+        self.buckets.push(SerializableBucket {
+            label,
+            binary: BucketCodec {
+                save: save_bucket::<T, Vec<u8>>,
+                decode: decode_bucket::<T, Vec<u8>>,
+            },
+            json: BucketCodec {
+                save: save_bucket::<T, serde_json::Value>,
+                decode: decode_bucket::<T, serde_json::Value>,
+            },
+        });
+    }
+
+    fn component(&self, name: &str) -> Option<&SerializableComponent> {
+        self.components
+            .iter()
+            .find(|component| component.name == name)
+    }
+
+    fn bucket(&self, label: &str) -> Option<&SerializableBucket> {
+        self.buckets.iter().find(|bucket| bucket.label == label)
+    }
+}
+
+fn save_component<T: 'static + Serialize, P: Payload>(
+    archetype: &Archetype,
+    row: usize,
+) -> Result<P, SnapshotError> {
+    let components = archetype
+        .components::<T>()
+        .expect("Archetype is missing a column listed in its types");
+
+    P::encode(&components[row])
+}
+
+fn decode_component<T: Component + DeserializeOwned, P: Payload>(
+    payload: P,
+) -> Result<ComponentInsert, SnapshotError> {
+    let component = payload.decode::<T>()?;
+
+    Ok(Box::new(move |world: &mut World, entity| {
+        world.add_component_to_entity(entity, component)
+    }))
+}
+
+fn save_bucket<T: 'static + Serialize, P: Payload>(
+    storage: &Storage,
+    label: &'static str,
+) -> Result<P, SnapshotError> {
+    let bucket = storage.try_query_storage::<T>(label)?;
+
+    P::encode(&*bucket)
+}
+
+fn decode_bucket<T: 'static + Send + Sync + DeserializeOwned, P: Payload>(
+    payload: P,
+) -> Result<BucketInsert, SnapshotError> {
+    let bucket = payload.decode::<T>()?;
+
+    Ok(Box::new(move |storage: &mut Storage, label| {
+        storage.new_bucket(label, bucket)
+    }))
+}
+
+/// Encodes every living entity with its serializable components, plus every registered
+/// bucket.
+pub(crate) fn encode(world: &World, format: SnapshotFormat) -> Result<Vec<u8>, SnapshotError> {
+    match format {
+        SnapshotFormat::Binary => {
+            let snapshot = save::<Vec<u8>>(world)?;
+
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(bincode::serialize(&snapshot)?);
+
+            Ok(bytes)
+        },
+        SnapshotFormat::Json => {
+            let snapshot = save::<serde_json::Value>(world)?;
+
+            Ok(serde_json::to_vec_pretty(&snapshot)?)
+        },
+    }
+}
+
+/// Decodes a snapshot of either format into `world`, which must not have living entities.
+pub(crate) fn decode(world: &mut World, bytes: &[u8]) -> Result<(), SnapshotError> {
+    match SnapshotFormat::detect(bytes) {
+        SnapshotFormat::Binary => {
+            // The version is the first field of the snapshot, and bincode writes a `u32` as 4
+            // little-endian bytes, so it can be checked before decoding the rest.
+            let body = &bytes[MAGIC.len()..];
+            let version = body
+                .get(..4)
+                .map(|version| u32::from_le_bytes(version.try_into().unwrap()))
+                .ok_or(SnapshotError::InvalidHeader)?;
+            check_version(version)?;
+
+            load(world, bincode::deserialize::<Snapshot<Vec<u8>>>(body)?)
+        },
+        SnapshotFormat::Json => {
+            let version = serde_json::from_slice::<SnapshotVersion>(bytes)
+                .map_err(|_| SnapshotError::InvalidHeader)?
+                .version;
+            check_version(version)?;
+
+            load(
+                world,
+                serde_json::from_slice::<Snapshot<serde_json::Value>>(bytes)?,
+            )
+        },
+    }
+}
+
+fn check_version(version: u32) -> Result<(), SnapshotError> {
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion {
+            found: version,
+            expected: SNAPSHOT_VERSION,
+        });
+    }
+
+    Ok(())
+}
+
+fn save<P: Payload>(world: &World) -> Result<Snapshot<P>, SnapshotError> {
+    let registry = &world.snapshot_registry;
+    let by_type: HashMap<TypeId, &SerializableComponent> = registry
+        .components
+        .iter()
+        .map(|component| (component.type_id, component))
+        .collect();
+    let mut skipped = HashSet::new();
+
+    let mut entities = Vec::with_capacity(world.entities.living_entity_count);
+    for entity in world.entities.iter() {
+        let location = world
+            .entities
+            .location(entity)
+            .expect("EntityManager::iter() returned a dead entity");
+        let archetype = world
+            .archetypes
+            .get(location.archetype)
+            .expect("Entity location points to a missing archetype");
+
+        let mut components = BTreeMap::new();
+        for type_id in archetype.types() {
+            match by_type.get(type_id) {
+                Some(component) => {
+                    let payload = (P::component_codec(component).save)(archetype, location.row)?;
+                    components.insert(component.name.to_string(), payload);
+                },
+                None => {
+                    skipped.insert(*type_id);
+                },
+            }
+        }
+
+        entities.push(EntitySnapshot {
+            id: entity.id,
+            generation: entity.generation,
+            components,
+        });
+    }
+
+    for type_id in skipped {
+        let label = world
+            .components
+            .get(type_id)
+            .map_or("<unknown>", |info| info.label);
+        warn!(
+            "World::serialize() - component {} is not registered as serializable, skipping it",
+            label
+        );
+    }
+
+    let storage = world.storage.read().expect("Could not read lock storage");
+    let mut buckets = BTreeMap::new();
+    for bucket in registry.buckets.iter() {
+        if !storage.contains(bucket.label) {
+            debug!(
+                "World::serialize() - bucket {} does not exist, skipping it",
+                bucket.label
+            );
+            continue;
+        }
+
+        let payload = (P::bucket_codec(bucket).save)(&storage, bucket.label)?;
+        buckets.insert(bucket.label.to_string(), payload);
+    }
+
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        entities,
+        free_entities: world.entities.free_slots(),
+        buckets,
+    })
+}
+
+/// A snapshot with every component and bucket decoded, which can no longer fail to load.
+struct Decoded {
+    entities: Vec<(Entity, Vec<ComponentInsert>)>,
+    free_entities: Vec<Entity>,
+    buckets: Vec<(&'static str, BucketInsert)>,
+}
+
+fn load<P: Payload>(world: &mut World, snapshot: Snapshot<P>) -> Result<(), SnapshotError> {
+    if world.entities.living_entity_count > 0 {
+        return Err(SnapshotError::WorldNotEmpty);
+    }
+
+    // Everything that can fail happens before the world is touched, so a snapshot that does
+    // not load leaves the world as it was.
+    validate(&world.snapshot_registry, &snapshot)?;
+    let decoded = decode_snapshot(&world.snapshot_registry, snapshot)?;
+    restore(world, decoded);
+
+    Ok(())
+}
+
+fn validate<P>(registry: &SnapshotRegistry, snapshot: &Snapshot<P>) -> Result<(), SnapshotError> {
+    let mut ids = HashSet::new();
+
+    for entity in snapshot.free_entities.iter() {
+        if !ids.insert(entity.id) {
+            return Err(SnapshotError::DuplicateEntity(entity.id));
+        }
+    }
+
+    for entity in snapshot.entities.iter() {
+        if !ids.insert(entity.id) {
+            return Err(SnapshotError::DuplicateEntity(entity.id));
+        }
+
+        for name in entity.components.keys() {
+            if registry.component(name).is_none() {
+                return Err(SnapshotError::UnknownComponent(name.clone()));
+            }
+        }
+    }
+
+    for label in snapshot.buckets.keys() {
+        if registry.bucket(label).is_none() {
+            return Err(SnapshotError::UnknownBucket(label.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_snapshot<P: Payload>(
+    registry: &SnapshotRegistry,
+    snapshot: Snapshot<P>,
+) -> Result<Decoded, SnapshotError> {
+    let mut entities = Vec::with_capacity(snapshot.entities.len());
+    for entity_snapshot in snapshot.entities {
+        let entity = Entity {
+            id: entity_snapshot.id,
+            generation: entity_snapshot.generation,
+        };
+
+        let mut components = Vec::with_capacity(entity_snapshot.components.len());
+        for (name, payload) in entity_snapshot.components {
+            let component = registry
+                .component(&name)
+                .expect("Snapshot component names were validated");
+            components.push((P::component_codec(component).decode)(payload)?);
+        }
+
+        entities.push((entity, components));
+    }
+
+    let mut buckets = Vec::with_capacity(snapshot.buckets.len());
+    for (label, payload) in snapshot.buckets {
+        let bucket = registry
+            .bucket(&label)
+            .expect("Snapshot bucket labels were validated");
+        buckets.push((bucket.label, (P::bucket_codec(bucket).decode)(payload)?));
+    }
+
+    Ok(Decoded {
+        entities,
+        free_entities: snapshot.free_entities,
+        buckets,
+    })
+}
+
+fn restore(world: &mut World, decoded: Decoded) {
+    for (entity, components) in decoded.entities {
+        let spawned = world.spawn_at(entity);
+        assert!(
+            spawned,
+            "Snapshot entity ids were validated, and the world has no living entities"
+        );
+
+        for insert in components {
+            insert(world, entity);
+        }
+    }
+
+    world.entities.restore_free_slots(&decoded.free_entities);
+
+    let storage = world.storage.get_mut().expect("Could not lock storage");
+    for (label, insert) in decoded.buckets {
+        insert(storage, label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{entity::Entity, Component, SnapshotError, SnapshotFormat, World};
+
+    #[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Position(i32, i32);
+
+    #[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Name(String);
+
+    fn new_world() -> World {
+        let mut world = World::new();
+        world.register_serializable::<Position>("Position");
+        world.register_serializable::<Name>("Name");
+        world.register_serializable_bucket::<Vec<u8>>("grid");
+        world
+    }
+
+    fn grid(world: &World) -> Option<Vec<u8>> {
+        let storage = world.storage.read().unwrap();
+        storage
+            .try_query_storage::<Vec<u8>>("grid")
+            .ok()
+            .map(|grid| grid.clone())
+    }
+
+    // Saves a world with a freed slot, changes and clears it, loads the snapshot back into it
+    // and checks everything matches the saved state.
+    fn round_trip(format: SnapshotFormat) {
+        let mut world = new_world();
+        let a = world.spawn_with((Position(1, 2), Name("a".to_string())));
+        let freed = world.spawn_with((Position(3, 4),));
+        let c = world.spawn_with((Name("c".to_string()),));
+        world.despawn(freed);
+        world
+            .storage
+            .write()
+            .unwrap()
+            .new_bucket::<Vec<u8>>("grid", vec![1, 2, 3]);
+
+        let bytes = world.serialize(format).unwrap();
+
+        // Mutate, then clear the world, freeing more slots than the snapshot knows about. The
+        // first extra entity reuses the freed slot, the others go in slots 3 and 4.
+        world.get_component_mut::<Position>(a).unwrap().0 = 100;
+        let extra: Vec<Entity> = (0..3).map(|_| world.spawn()).collect();
+        for entity in [a, c].into_iter().chain(extra.iter().copied()) {
+            world.despawn(entity);
+        }
+        world
+            .storage
+            .write()
+            .unwrap()
+            .new_bucket::<Vec<u8>>("grid", vec![9]);
+
+        world.deserialize(&bytes).unwrap();
+
+        assert_eq!(world.entities.living_entity_count, 2);
+        assert_eq!(world.get_component::<Position>(a), Some(&Position(1, 2)));
+        assert_eq!(world.get_component::<Name>(a), Some(&Name("a".to_string())));
+        assert_eq!(world.get_component::<Name>(c), Some(&Name("c".to_string())));
+        assert!(!world.has_component::<Position>(c));
+        assert!(!world.is_alive(freed));
+        assert_eq!(grid(&world), Some(vec![1, 2, 3]));
+
+        // The snapshot's free slot comes back first, under the handle it had in the saved
+        // world, and the slots freed after saving are still reused rather than leaked.
+        let respawned = world.spawn();
+        assert_eq!(respawned.id, freed.id);
+        assert_eq!(respawned.generation, freed.generation + 1);
+
+        let slots = world.entities.id_counter;
+        let reused: Vec<usize> = (0..slots - 3).map(|_| world.spawn().id).collect();
+        assert_eq!(reused, vec![3, 4]);
+        assert_eq!(world.spawn().id, slots);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(SnapshotFormat::Json);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip(SnapshotFormat::Binary);
+    }
+
+    #[test]
+    fn failed_load_leaves_world_untouched() {
+        let mut world = new_world();
+        world.spawn_with((Position(1, 2),));
+        world.spawn_with((Position(3, 4),));
+        world
+            .storage
+            .write()
+            .unwrap()
+            .new_bucket::<Vec<u8>>("grid", vec![1, 2, 3]);
+
+        let mut snapshot: serde_json::Value =
+            serde_json::from_slice(&world.serialize(SnapshotFormat::Json).unwrap()).unwrap();
+        // The first entity and the bucket decode fine, the second entity does not.
+        snapshot["entities"][1]["components"]["Position"] = serde_json::json!("not a position");
+        let bytes = serde_json::to_vec(&snapshot).unwrap();
+
+        let mut restored = new_world();
+        restored
+            .storage
+            .write()
+            .unwrap()
+            .new_bucket::<Vec<u8>>("grid", vec![9]);
+
+        let result = restored.deserialize(&bytes);
+
+        assert!(matches!(result, Err(SnapshotError::Json(_))));
+        assert_eq!(restored.entities.living_entity_count, 0);
+        assert_eq!(restored.entities.iter().count(), 0);
+        assert_eq!(grid(&restored), Some(vec![9]));
+    }
+
+    #[test]
+    fn load_into_world_with_living_entities_fails() {
+        let mut world = new_world();
+        world.spawn_with((Position(1, 2),));
+        let bytes = world.serialize(SnapshotFormat::Binary).unwrap();
+
+        assert!(matches!(
+            world.deserialize(&bytes),
+            Err(SnapshotError::WorldNotEmpty)
+        ));
+    }
+}
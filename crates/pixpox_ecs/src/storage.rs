@@ -1,44 +1,358 @@
 use lasso::{Spur, ThreadedRodeo};
 use log::debug;
 
-use std::{any::Any, collections::HashMap};
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+use thiserror::Error;
 
+use crate::change_detection::{ChangeTick, ComponentTicks};
+use crate::event::Events;
 use crate::GlobalPixelMap as GlobalPixelMapTrait;
 use crate::Texture;
 pub use pixpox_utils::InputHandler;
 
+/// Label of the bucket holding the global pixel map.
+const PIXELMAP: &str = "pixelmap";
+
 pub enum BucketAction {
     GET,
     PUT,
 }
 
+/// Every way a storage query can fail.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// No bucket was ever created with this label, which usually means it is misspelled.
+    #[error("No bucket was ever created with label `{0}`")]
+    UnknownLabel(&'static str),
+    /// The label is known, but its bucket has been removed or taken.
+    #[error("Bucket `{0}` has been removed")]
+    MissingBucket(&'static str),
+    /// The bucket exists but holds a value of another type.
+    #[error("Bucket `{label}` holds a `{actual}`, not a `{expected}`")]
+    TypeMismatch {
+        label: &'static str,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+type BucketData = Box<dyn Any + Send + Sync>;
+
+/// A single bucket or resource, locked independently of every other one.
+struct Bucket {
+    data: RwLock<BucketData>,
+    type_id: TypeId,
+    type_name: &'static str,
+    added: u32,
+    changed: AtomicU32,
+}
+
+impl Bucket {
+    fn new<T: 'static + Send + Sync>(data: T, change_tick: u32) -> Self {
+        Self {
+            data: RwLock::new(Box::new(data)),
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            added: change_tick,
+            changed: AtomicU32::new(change_tick),
+        }
+    }
+
+    fn ticks(&self) -> ComponentTicks {
+        ComponentTicks {
+            added: self.added,
+            changed: self.changed.load(Ordering::Acquire),
+        }
+    }
+
+    fn check<T: 'static>(&self, label: &'static str) -> Result<(), StorageError> {
+        if self.type_id == TypeId::of::<T>() {
+            Ok(())
+        } else {
+            Err(StorageError::TypeMismatch {
+                label,
+                expected: type_name::<T>(),
+                actual: self.type_name,
+            })
+        }
+    }
+
+    fn read<T: 'static>(&self, label: &'static str) -> Result<BucketRef<'_, T>, StorageError> {
+        self.check::<T>(label)?;
+
+        Ok(BucketRef {
+            guard: self.data.read().expect("Could not read lock bucket"),
+            marker: PhantomData,
+        })
+    }
+
+    fn write<T: 'static>(
+        &self,
+        label: &'static str,
+        change_tick: u32,
+    ) -> Result<BucketMut<'_, T>, StorageError> {
+        self.check::<T>(label)?;
+
+        Ok(BucketMut {
+            guard: self.data.write().expect("Could not write lock bucket"),
+            changed: &self.changed,
+            change_tick,
+            marker: PhantomData,
+        })
+    }
+
+    /// Exclusive access without locking; the bucket is marked changed right away.
+    fn get_mut<T: 'static>(
+        &mut self,
+        label: &'static str,
+        change_tick: u32,
+    ) -> Result<&mut T, StorageError> {
+        self.check::<T>(label)?;
+        *self.changed.get_mut() = change_tick;
+
+        Ok(self
+            .data
+            .get_mut()
+            .expect("Could not lock bucket")
+            .downcast_mut::<T>()
+            .expect("Bucket type was checked before downcasting"))
+    }
+
+    fn into_inner<T: 'static>(self) -> T {
+        *self
+            .data
+            .into_inner()
+            .expect("Could not lock bucket")
+            .downcast::<T>()
+            .expect("Bucket type was checked before downcasting")
+    }
+}
+
+/// A read lock on one bucket, dereferencing to its value.
+pub struct BucketRef<'s, T> {
+    guard: RwLockReadGuard<'s, BucketData>,
+    marker: PhantomData<T>,
+}
+
+impl<'s, T: 'static> Deref for BucketRef<'s, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard
+            .downcast_ref::<T>()
+            .expect("Bucket type was checked when it was locked")
+    }
+}
+
+/// A write lock on one bucket, dereferencing to its value. Writing through it marks the bucket
+/// changed.
+pub struct BucketMut<'s, T> {
+    guard: RwLockWriteGuard<'s, BucketData>,
+    changed: &'s AtomicU32,
+    change_tick: u32,
+    marker: PhantomData<T>,
+}
+
+impl<'s, T: 'static> Deref for BucketMut<'s, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard
+            .downcast_ref::<T>()
+            .expect("Bucket type was checked when it was locked")
+    }
+}
+
+impl<'s, T: 'static> BucketMut<'s, T> {
+    /// Writes through without marking the bucket changed.
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.guard
+            .downcast_mut::<T>()
+            .expect("Bucket type was checked when it was locked")
+    }
+
+    /// Marks the bucket changed, e.g. after writing through `bypass_change_detection()`.
+    pub fn set_changed(&mut self) {
+        self.changed.store(self.change_tick, Ordering::Release);
+    }
+}
+
+impl<'s, T: 'static> DerefMut for BucketMut<'s, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.changed.store(self.change_tick, Ordering::Release);
+        self.guard
+            .downcast_mut::<T>()
+            .expect("Bucket type was checked when it was locked")
+    }
+}
+
+/// One bucket in a `Storage::lock_buckets()` call: `&T` takes a read lock, `&mut T` a write lock.
+pub trait BucketLock {
+    type Guard<'s>;
+
+    #[doc(hidden)]
+    fn lock<'s>(
+        storage: &'s Storage,
+        key: Spur,
+        label: &'static str,
+    ) -> Result<Self::Guard<'s>, StorageError>;
+}
+
+impl<T: 'static> BucketLock for &T {
+    type Guard<'s> = BucketRef<'s, T>;
+
+    fn lock<'s>(
+        storage: &'s Storage,
+        key: Spur,
+        label: &'static str,
+    ) -> Result<Self::Guard<'s>, StorageError> {
+        storage.buckets[&key].read::<T>(label)
+    }
+}
+
+impl<T: 'static> BucketLock for &mut T {
+    type Guard<'s> = BucketMut<'s, T>;
+
+    fn lock<'s>(
+        storage: &'s Storage,
+        key: Spur,
+        label: &'static str,
+    ) -> Result<Self::Guard<'s>, StorageError> {
+        storage.buckets[&key].write::<T>(label, storage.change_tick.get())
+    }
+}
+
+/// A tuple of `BucketLock`s, e.g. `(&mut ConwayGrid, &GlobalPixelMap)`, locked together by
+/// `Storage::lock_buckets()`.
+pub trait BucketLocks {
+    type Labels;
+    type Guards<'s>;
+
+    #[doc(hidden)]
+    fn lock<'s>(
+        storage: &'s Storage,
+        labels: Self::Labels,
+    ) -> Result<Self::Guards<'s>, StorageError>;
+}
+
+macro_rules! impl_bucket_locks_for_tuple {
+    ($count:literal; $($name:ident $index:tt),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: BucketLock),+> BucketLocks for ($($name,)+) {
+            type Labels = [&'static str; $count];
+            type Guards<'s> = ($($name::Guard<'s>,)+);
+
+            fn lock<'s>(storage: &'s Storage, labels: Self::Labels) -> Result<Self::Guards<'s>, StorageError> {
+                let mut keys = [None; $count];
+                for (i, label) in labels.iter().enumerate() {
+                    keys[i] = Some(storage.key(label)?);
+                }
+                let keys = keys.map(|key| key.expect("Every label was looked up"));
+
+                for (i, key) in keys.iter().enumerate() {
+                    if keys[i + 1..].contains(key) {
+                        panic!("Storage::lock_buckets() - bucket {} is locked twice", labels[i]);
+                    }
+                }
+
+                // Always acquire locks in key order, so that two calls locking overlapping
+                // buckets can never wait on each other.
+                let mut order: Vec<usize> = (0..$count).collect();
+                order.sort_by_key(|&i| keys[i]);
+
+                $(let mut $name = None;)+
+                for i in order {
+                    match i {
+                        $($index => $name = Some($name::lock(storage, keys[i], labels[i])?),)+
+                        _ => unreachable!(),
+                    }
+                }
+
+                Ok(($($name.expect("Every bucket was locked"),)+))
+            }
+        }
+    };
+}
+
+impl_bucket_locks_for_tuple!(1; A 0);
+impl_bucket_locks_for_tuple!(2; A 0, B 1);
+impl_bucket_locks_for_tuple!(3; A 0, B 1, C 2);
+impl_bucket_locks_for_tuple!(4; A 0, B 1, C 2, D 3);
+impl_bucket_locks_for_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+impl_bucket_locks_for_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+
 /// # Storage
 ///
 /// Storage is a data structure designed to store global data accessible to all components.
 ///
 /// It introduces a query system to create and retrieve buckets through a simple-to-use API.
 ///
+/// Singletons can also be stored as resources, which are keyed by their type instead of a
+/// label. Use resources whenever there is only ever one value of a type, and buckets when
+/// several values share a type.
+///
 /// To optimize bucket lookup, Storage implements a multi-threaded interner that efficiently stores bucket
 /// labels and associates them with Spurs. This interner facilitates fast and efficient hashmap lookup.
 ///
 /// ## Example
 ///
 /// ```
-/// let mut storage = app.world.storage.write().unwrap();
+/// use pixpox_ecs::World;
+/// use pixpox_utils::conway::ConwayGrid;
 ///
-/// let (width, height) = (cfg.window_width, cfg.window_height);
-/// storage.new_bucket::<(u32, u32)>("grid-size", (width, height));
+/// let world = World::new();
+/// let mut storage = world.storage.write().unwrap();
 ///
-/// let (width, height) = storage
-///     .query_storage::<HashMap<LogicalPosition<u32>, bool>>("grid-size")
+/// storage.new_bucket::<(u32, u32)>("grid-size", (300, 200));
+///
+/// let (width, height) = *storage
+///     .query_storage::<(u32, u32)>("grid-size")
 ///     .expect("Could not query storage: grid-size");
-
-/// let mut (width, height) = storage
-///     .query_storage_mut::<HashMap<LogicalPosition<u32>, bool>>("grid-size")
+///
+/// let size = storage
+///     .query_storage_mut::<(u32, u32)>("grid-size")
 ///     .expect("Could not query storage: grid-size");
+/// size.0 += 1;
 ///
+/// storage.insert_resource(ConwayGrid::new(height, width, 0.1));
+///
+/// let grid = storage
+///     .resource_mut::<ConwayGrid>()
+///     .expect("Could not query resource: ConwayGrid");
+/// grid.clear_grid();
+/// ```
+///
+/// ## Locking
+///
+/// Every bucket and resource has its own `RwLock`, so components updating in parallel only need
+/// a read lock on the `Storage` itself and can then lock exactly the buckets they touch:
+///
+/// ```ignore
+/// let storage = rw_storage.read().unwrap();
+///
+/// let mut grid = storage.write_bucket::<ConwayGrid>("optim_grid").unwrap();
+/// let (mut grid, mut pixelmap) = storage
+///     .lock_buckets::<(&mut ConwayGrid, &mut GlobalPixelMap)>(["optim_grid", "pixelmap"])
+///     .unwrap();
 /// ```
 ///
+/// Use `lock_buckets()` whenever more than one bucket is held at a time: it always acquires the
+/// locks in the same order, so two threads locking overlapping buckets cannot deadlock.
+///
+/// ## Errors
+///
+/// The `try_query*` methods return a `StorageError` telling apart a label that was never used,
+/// a bucket that has been removed, and a bucket holding another type.
+///
 /// ## Panics
 ///
 /// - If the bucket associated with the given label is not found, `query_storage()` and `query_storage_mut()`
@@ -49,123 +363,215 @@ pub enum BucketAction {
 ///
 /// ## Safety
 ///
-/// - The data stored in the `Storage` is not guaranteed to be thread-safe by default.
-/// If you need thread-safety, you can use a `RwLock` or a `Mutex` to synchronize access to the `Storage`.
+/// - Holding a bucket lock while locking another bucket outside of `lock_buckets()` can deadlock.
 
 pub struct Storage {
-    pub buckets: HashMap<Spur, Box<dyn Any + Send + Sync>>,
+    buckets: HashMap<Spur, Bucket>,
+    resources: HashMap<TypeId, Bucket>,
     interner: ThreadedRodeo,
+    change_tick: ChangeTick,
 }
 
 impl Storage {
     pub fn new() -> Self {
+        Self::with_change_tick(ChangeTick::new())
+    }
+
+    /// Creates a storage that stamps bucket changes with the given (usually a world's) tick.
+    pub fn with_change_tick(change_tick: ChangeTick) -> Self {
         Self {
             buckets: HashMap::new(),
+            resources: HashMap::new(),
             interner: ThreadedRodeo::new(),
+            change_tick,
         }
     }
 
-    pub fn query_storage<T: 'static>(&self, label: &'static str) -> Option<&T> {
-        let key = &self
+    fn key(&self, label: &'static str) -> Result<Spur, StorageError> {
+        let key = self
             .interner
             .get(label)
-            .expect("String interner could not find a label");
+            .ok_or(StorageError::UnknownLabel(label))?;
 
-        assert!(
-            self.buckets.contains_key(key),
-            "World::query_storage() didn't find an item you were looking for."
-        );
+        if self.buckets.contains_key(&key) {
+            Ok(key)
+        } else {
+            Err(StorageError::MissingBucket(label))
+        }
+    }
 
-        if let Some(data) = self.buckets.get(key) {
-            // debug!("Storage::query_storage() - label found");
-            if let Some(downcasted) = data.downcast_ref::<T>() {
-                // debug!("Storage::query_storage() - value downcasted successfully");
-                return Some(downcasted);
-            }
+    /// Read locks the bucket with this label.
+    pub fn try_query_storage<T: 'static>(
+        &self,
+        label: &'static str,
+    ) -> Result<BucketRef<'_, T>, StorageError> {
+        self.buckets[&self.key(label)?].read::<T>(label)
+    }
+
+    /// Write locks the bucket with this label.
+    pub fn try_write_storage<T: 'static>(
+        &self,
+        label: &'static str,
+    ) -> Result<BucketMut<'_, T>, StorageError> {
+        self.buckets[&self.key(label)?].write::<T>(label, self.change_tick.get())
+    }
+
+    /// Exclusive access to the bucket with this label, without locking it.
+    pub fn try_query_storage_mut<T: 'static>(
+        &mut self,
+        label: &'static str,
+    ) -> Result<&mut T, StorageError> {
+        let key = self.key(label)?;
+
+        self.buckets
+            .get_mut(&key)
+            .expect("Storage::key() returned a missing bucket")
+            .get_mut::<T>(label, self.change_tick.get())
+    }
+
+    /// Panicking version of `try_query_storage()`; only a type mismatch returns `None`.
+    pub fn query_storage<T: 'static>(&self, label: &'static str) -> Option<BucketRef<'_, T>> {
+        match self.try_query_storage::<T>(label) {
+            Ok(data) => Some(data),
+            Err(StorageError::TypeMismatch { .. }) => None,
+            Err(err) => panic!("Storage::query_storage() - {}", err),
         }
+    }
 
-        None
+    /// Panicking version of `try_write_storage()`; only a type mismatch returns `None`.
+    pub fn write_storage<T: 'static>(&self, label: &'static str) -> Option<BucketMut<'_, T>> {
+        match self.try_write_storage::<T>(label) {
+            Ok(data) => Some(data),
+            Err(StorageError::TypeMismatch { .. }) => None,
+            Err(err) => panic!("Storage::write_storage() - {}", err),
+        }
     }
 
+    /// Panicking version of `try_query_storage_mut()`; only a type mismatch returns `None`.
     pub fn query_storage_mut<T: 'static>(&mut self, label: &'static str) -> Option<&mut T> {
-        let key = &self
-            .interner
-            .get(label)
-            .expect("String interner could not find a label");
+        match self.try_query_storage_mut::<T>(label) {
+            Ok(data) => Some(data),
+            Err(StorageError::TypeMismatch { .. }) => None,
+            Err(err) => panic!("Storage::query_storage_mut() - {}", err),
+        }
+    }
 
-        assert!(
-            self.buckets.contains_key(key),
-            "World::query_storage_mut() didn't find an item you were looking for."
-        );
+    /// Locks several buckets at once, read locking `&T` entries and write locking `&mut T`
+    /// entries. Panics if the same bucket is listed twice.
+    pub fn lock_buckets<L: BucketLocks>(
+        &self,
+        labels: L::Labels,
+    ) -> Result<L::Guards<'_>, StorageError> {
+        L::lock(self, labels)
+    }
 
-        if let Some(data) = self.buckets.get_mut(key) {
-            // debug!("Storage::query_storage() - label found");
-            if let Some(downcasted) = data.downcast_mut::<T>() {
-                // debug!("Storage::query_storage() - value downcasted successfully");
-                return Some(downcasted);
-            }
-        }
+    pub fn try_query_global_pixel_map<T: 'static + GlobalPixelMapTrait>(
+        &mut self,
+    ) -> Result<&mut dyn GlobalPixelMapTrait, StorageError> {
+        self.try_query_storage_mut::<T>(PIXELMAP)
+            .map(|pixelmap| pixelmap as &mut dyn GlobalPixelMapTrait)
+    }
 
-        None
+    pub fn query_global_pixel_map<T: 'static + GlobalPixelMapTrait>(
+        &mut self,
+    ) -> Option<&mut dyn GlobalPixelMapTrait> {
+        match self.try_query_global_pixel_map::<T>() {
+            Ok(pixelmap) => Some(pixelmap),
+            Err(StorageError::TypeMismatch { .. }) => None,
+            Err(err) => panic!("Storage::query_global_pixel_map() - {}", err),
+        }
     }
 
-    pub fn query_global_pixel_map<T: 'static + GlobalPixelMapTrait>(&mut self) -> Option<&mut dyn GlobalPixelMapTrait> {
-        let key = &self
-            .interner
-            .get("pixelmap")
-            .expect("String interner could not find a label");
+    /// Lets the global pixel map handle input. It is only marked changed if its camera moved,
+    /// so a frame can skip rendering a pixel map nothing has drawn on or moved.
+    pub fn try_update_global_pixel_map<T: 'static + GlobalPixelMapTrait>(
+        &self,
+        input: &InputHandler,
+    ) -> Result<(), StorageError> {
+        let mut pixelmap = self.try_write_storage::<T>(PIXELMAP)?;
 
-        assert!(
-            self.buckets.contains_key(key),
-            "World::query_global_pixel_map() didn't find an item you were looking for."
-        );
+        debug!("Updating global pixel map");
+        let camera = pixelmap.get_camera();
+        pixelmap.bypass_change_detection().update(input);
 
-        if let Some(data) = self.buckets.get_mut(key) {
-            if let Some(downcasted) = data.downcast_mut::<T>() {
-                return Some(downcasted);
-            }
+        if pixelmap.get_camera() != camera {
+            pixelmap.set_changed();
         }
 
-        None
+        Ok(())
     }
 
-    pub fn update_global_pixel_map<T: 'static + GlobalPixelMapTrait>(&mut self, input: &InputHandler) {
-        let key = &self
-            .interner
-            .get("pixelmap")
-            .expect("String interner could not find a label");
+    /// Panicking version of `try_update_global_pixel_map()`; a pixel map of another type is
+    /// left untouched.
+    pub fn update_global_pixel_map<T: 'static + GlobalPixelMapTrait>(
+        &self,
+        input: &InputHandler,
+    ) {
+        match self.try_update_global_pixel_map::<T>(input) {
+            Ok(()) | Err(StorageError::TypeMismatch { .. }) => {},
+            Err(err) => panic!("Storage::update_global_pixel_map() - {}", err),
+        }
+    }
 
-        assert!(
-            self.buckets.contains_key(key),
-            "World::update_global_pixel_map() didn't find an item you were looking for."
-        );
+    /// Read locks the global pixel map, e.g. to render it.
+    pub fn global_pixel_map<T: 'static + GlobalPixelMapTrait>(
+        &self,
+    ) -> Result<BucketRef<'_, T>, StorageError> {
+        self.try_query_storage::<T>(PIXELMAP)
+    }
 
-        if let Some(data) = self.buckets.get_mut(key) {
-            if let Some(downcasted) = data.downcast_mut::<T>() {
-                debug!("Updating global pixel map");
-                debug!("Updating global pixel map");
-                debug!("Updating global pixel map");
-                debug!("Updating global pixel map");
-                debug!("Updating global pixel map");
-                debug!("Updating global pixel map");
-                downcasted.update(input);
-            }
-        }
+    /// When the bucket with this label was created and last written to.
+    pub fn bucket_ticks(&self, label: &'static str) -> Result<ComponentTicks, StorageError> {
+        Ok(self.buckets[&self.key(label)?].ticks())
+    }
+
+    /// When the global pixel map was created and last written to.
+    pub fn global_pixel_map_ticks(&self) -> Result<ComponentTicks, StorageError> {
+        self.bucket_ticks(PIXELMAP)
+    }
+
+    /// Returns `true` if a bucket with this label currently exists.
+    pub fn contains(&self, label: &'static str) -> bool {
+        self.key(label).is_ok()
+    }
+
+    /// Drops the bucket with this label.
+    pub fn remove_bucket(&mut self, label: &'static str) -> Result<(), StorageError> {
+        let key = self.key(label)?;
+        self.buckets.remove(&key);
+
+        Ok(())
+    }
+
+    /// Removes the bucket with this label and returns its value. On a type mismatch the bucket
+    /// is left in place.
+    pub fn take<T: 'static>(&mut self, label: &'static str) -> Result<T, StorageError> {
+        let key = self.key(label)?;
+        self.buckets[&key].check::<T>(label)?;
+
+        let bucket = self
+            .buckets
+            .remove(&key)
+            .expect("Storage::key() returned a missing bucket");
+
+        Ok(bucket.into_inner::<T>())
     }
 
     pub fn new_global_pixel_map<T: 'static + GlobalPixelMapTrait + Send + Sync>(
         &mut self,
         pixelmap: T,
     ) {
-        let key = self.interner.get_or_intern("pixelmap");
+        let key = self.interner.get_or_intern(PIXELMAP);
 
-        self.buckets.insert(key, Box::new(pixelmap));
+        self.buckets
+            .insert(key, Bucket::new(pixelmap, self.change_tick.get()));
     }
 
     pub fn new_bucket<T: 'static + Send + Sync>(&mut self, label: &'static str, data: T) {
         let key = self.interner.get_or_intern(label);
 
-        self.buckets.insert(key, Box::new(data));
+        self.buckets
+            .insert(key, Bucket::new(data, self.change_tick.get()));
     }
 
     pub fn new_hashmap_bucket<K: 'static + Send + Sync, V: 'static + Send + Sync>(
@@ -174,15 +580,174 @@ impl Storage {
         default: Option<HashMap<K, V>>,
     ) {
         let key = self.interner.get_or_intern(label);
-        let datastorage = Box::new(HashMap::<K, V>::new());
-
-        match default {
-            Some(map) => {
-                self.buckets.insert(key, Box::new(map));
-            },
-            None => {
-                self.buckets.insert(key, datastorage);
-            },
+        let map = default.unwrap_or_default();
+
+        self.buckets
+            .insert(key, Bucket::new(map, self.change_tick.get()));
+    }
+
+    /// Stores `resource` as the single value of its type, returning the one it replaced.
+    pub fn insert_resource<T: 'static + Send + Sync>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(
+                TypeId::of::<T>(),
+                Bucket::new(resource, self.change_tick.get()),
+            )
+            .map(|previous| previous.into_inner::<T>())
+    }
+
+    /// Read locks the resource of type `T`.
+    pub fn resource<T: 'static>(&self) -> Option<BucketRef<'_, T>> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .and_then(|resource| resource.read::<T>(type_name::<T>()).ok())
+    }
+
+    /// Write locks the resource of type `T`.
+    pub fn write_resource<T: 'static>(&self) -> Option<BucketMut<'_, T>> {
+        self.resources.get(&TypeId::of::<T>()).and_then(|resource| {
+            resource
+                .write::<T>(type_name::<T>(), self.change_tick.get())
+                .ok()
+        })
+    }
+
+    /// Exclusive access to the resource of type `T`, without locking it.
+    pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        let change_tick = self.change_tick.get();
+
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|resource| resource.get_mut::<T>(type_name::<T>(), change_tick).ok())
+    }
+
+    /// Read locks the `Events<E>` channel added with `World::add_event::<E>()`. Events can be
+    /// sent and read through the read lock.
+    pub fn events<E: 'static + Send + Sync>(&self) -> Option<BucketRef<'_, Events<E>>> {
+        self.resource::<Events<E>>()
+    }
+
+    /// When the resource of type `T` was inserted and last written to.
+    pub fn resource_ticks<T: 'static>(&self) -> Option<ComponentTicks> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .map(|resource| resource.ticks())
+    }
+
+    pub fn contains_resource<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|resource| resource.into_inner::<T>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_label() {
+        let mut storage = Storage::new();
+        storage.new_bucket::<u32>("grid", 3);
+
+        let err = StorageError::UnknownLabel("gird");
+        assert_eq!(
+            storage.try_query_storage::<u32>("gird").err(),
+            Some(err.clone())
+        );
+        assert_eq!(
+            storage.try_write_storage::<u32>("gird").err(),
+            Some(err.clone())
+        );
+        assert_eq!(storage.remove_bucket("gird"), Err(err.clone()));
+        assert_eq!(storage.take::<u32>("gird"), Err(err));
+    }
+
+    #[test]
+    fn missing_bucket() {
+        let mut storage = Storage::new();
+        storage.new_bucket::<u32>("grid", 3);
+        storage.new_bucket::<u32>("size", 4);
+
+        assert_eq!(storage.remove_bucket("grid"), Ok(()));
+        assert_eq!(storage.take::<u32>("size"), Ok(4));
+
+        for label in ["grid", "size"] {
+            let err = StorageError::MissingBucket(label);
+            assert!(!storage.contains(label));
+            assert_eq!(
+                storage.try_query_storage::<u32>(label).err(),
+                Some(err.clone())
+            );
+            assert_eq!(
+                storage.try_query_storage_mut::<u32>(label).err(),
+                Some(err.clone())
+            );
+            assert_eq!(storage.take::<u32>(label), Err(err));
         }
     }
+
+    #[test]
+    fn type_mismatch() {
+        let mut storage = Storage::new();
+        storage.new_bucket::<u32>("grid", 3);
+
+        let err = StorageError::TypeMismatch {
+            label: "grid",
+            expected: "f32",
+            actual: "u32",
+        };
+        assert_eq!(err.to_string(), "Bucket `grid` holds a `u32`, not a `f32`");
+        assert_eq!(
+            storage.try_query_storage::<f32>("grid").err(),
+            Some(err.clone())
+        );
+        assert_eq!(
+            storage.try_write_storage::<f32>("grid").err(),
+            Some(err.clone())
+        );
+        assert_eq!(storage.take::<f32>("grid"), Err(err));
+
+        // The bucket is left in place
+        assert!(storage.query_storage::<f32>("grid").is_none());
+        assert_eq!(storage.take::<u32>("grid"), Ok(3));
+    }
+
+    #[test]
+    fn opposite_lock_orders_do_not_deadlock() {
+        let mut storage = Storage::new();
+        storage.new_bucket::<u64>("a", 0);
+        storage.new_bucket::<u64>("b", 0);
+
+        std::thread::scope(|scope| {
+            for labels in [["a", "b"], ["b", "a"]] {
+                let storage = &storage;
+                scope.spawn(move || {
+                    for _ in 0..10_000 {
+                        let (mut first, mut second) = storage
+                            .lock_buckets::<(&mut u64, &mut u64)>(labels)
+                            .expect("Could not lock buckets");
+                        *first += 1;
+                        *second += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*storage.query_storage::<u64>("a").unwrap(), 20_000);
+        assert_eq!(*storage.query_storage::<u64>("b").unwrap(), 20_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket a is locked twice")]
+    fn locking_a_bucket_twice_panics() {
+        let mut storage = Storage::new();
+        storage.new_bucket::<u64>("a", 0);
+
+        let _ = storage.lock_buckets::<(&u64, &u64)>(["a", "a"]);
+    }
 }
@@ -0,0 +1,707 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        RwLock, RwLockReadGuard,
+    },
+    time::Instant,
+};
+
+use log::{debug, warn};
+use pixpox_utils::{stats::Stats, InputHandler};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    change_detection::Ticks,
+    command::Commands,
+    entity::Entity,
+    query::{self, Access, Filter, Query},
+    BucketMut, BucketRef, Storage, World,
+};
+
+type SystemFn = Box<dyn Fn(&mut SystemContext) + Send + Sync>;
+
+/// # Stage
+///
+/// The phases `World::run()` goes through on every tick, in this order:
+///
+/// 1. `Startup` systems, each exactly once, before anything else on the first tick after they
+///    were added. Commands they queue are applied right away, so they are the place for setup.
+/// 2. `PreUpdate` systems, e.g. to turn input into events.
+/// 3. `Run`: every component's `Run`, then the `Run` systems.
+/// 4. `Update`: every component's `Update`, then the `Update` systems.
+/// 5. `PostUpdate` systems, e.g. transform propagation, followed by a sync point that applies
+///    every queued command.
+/// 6. `PreRender` systems, after which the global pixel map is updated and the commands queued
+///    since the sync point are applied.
+///
+/// Systems run in `Update` unless they are moved with `System::in_stage()`. Within a stage
+/// they are ordered as described for `System`; `before()` and `after()` only work between
+/// systems of the same stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Startup,
+    PreUpdate,
+    Run,
+    #[default]
+    Update,
+    PostUpdate,
+    PreRender,
+}
+
+impl Stage {
+    /// Every stage, in the order they run.
+    pub const ALL: [Stage; 6] = [
+        Stage::Startup,
+        Stage::PreUpdate,
+        Stage::Run,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::PreRender,
+    ];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Startup => "Startup",
+            Stage::PreUpdate => "PreUpdate",
+            Stage::Run => "Run",
+            Stage::Update => "Update",
+            Stage::PostUpdate => "PostUpdate",
+            Stage::PreRender => "PreRender",
+        }
+    }
+
+    /// Label of the `Stats` sector timing the stage's systems.
+    pub(crate) fn systems_sector(self) -> &'static str {
+        match self {
+            Stage::Startup => "Startup systems",
+            Stage::PreUpdate => "PreUpdate systems",
+            Stage::Run => "Run systems",
+            Stage::Update => "Update systems",
+            Stage::PostUpdate => "PostUpdate systems",
+            Stage::PreRender => "PreRender systems",
+        }
+    }
+}
+
+/// # System
+///
+/// A free function that runs once per tick in its `Stage`, `Update` by default, i.e. after
+/// every component's `Run` and `Update`.
+///
+/// A system declares up front which component types, `Storage` buckets and resources it reads
+/// and writes. The `Schedule` uses these declarations to run systems that do not conflict at the
+/// same time on the rayon thread pool, and `SystemContext` panics if a system touches anything
+/// it did not declare.
+///
+/// Systems that conflict run in the order they were added, unless `before()` / `after()`
+/// say otherwise. Both take a label, which is either another system's name or a label added
+/// with `label()`.
+///
+/// ## Example
+///
+/// ```ignore
+/// world.add_system(
+///     System::new("gravity", |ctx| {
+///         for (_, pos, vel) in ctx.query::<(&mut Position, &Velocity)>() {
+///             pos.apply(vel);
+///         }
+///     })
+///     .writes::<Position>()
+///     .reads::<Velocity>()
+///     .label("simulation")
+///     .after("input"),
+/// );
+/// ```
+pub struct System {
+    name: &'static str,
+    stage: Stage,
+    labels: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    access: Access,
+    last_run: AtomicU32,
+    func: SystemFn,
+}
+
+impl System {
+    pub fn new(
+        name: &'static str,
+        func: impl Fn(&mut SystemContext) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            stage: Stage::default(),
+            labels: vec![name],
+            before: Vec::new(),
+            after: Vec::new(),
+            access: Access::new(),
+            last_run: AtomicU32::new(0),
+            func: Box::new(func),
+        }
+    }
+
+    /// Declares that the system reads components of type `T`.
+    pub fn reads<T: 'static>(mut self) -> Self {
+        self.access.add_read::<T>();
+        self
+    }
+
+    /// Declares that the system writes components of type `T`.
+    pub fn writes<T: 'static>(mut self) -> Self {
+        self.access.add_write::<T>();
+        self
+    }
+
+    /// Declares that the system reads the `Storage` bucket with the given label.
+    pub fn reads_bucket(mut self, label: &'static str) -> Self {
+        self.access.add_bucket_read(label);
+        self
+    }
+
+    /// Declares that the system writes the `Storage` bucket with the given label.
+    pub fn writes_bucket(mut self, label: &'static str) -> Self {
+        self.access.add_bucket_write(label);
+        self
+    }
+
+    /// Declares that the system reads the resource of type `T`.
+    pub fn reads_resource<T: 'static>(mut self) -> Self {
+        self.access.add_resource_read::<T>();
+        self
+    }
+
+    /// Declares that the system writes the resource of type `T`.
+    pub fn writes_resource<T: 'static>(mut self) -> Self {
+        self.access.add_resource_write::<T>();
+        self
+    }
+
+    /// Moves the system to another stage of the tick.
+    pub fn in_stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    /// Adds a label other systems can order themselves against.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Runs this system before every system with the given label.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Runs this system after every system with the given label.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    fn run(&self, world: &World) {
+        let _span = world.tracer.span("system", self.name);
+        let now = Instant::now();
+
+        // Every run gets its own tick and leaves the counter one ahead of it, so anything written
+        // after this point, by later systems, components or between ticks, is newer than
+        // `this_run` and is seen by this system on its next run.
+        let this_run = world.increment_change_tick().wrapping_sub(1);
+        let last_run = self.last_run.swap(this_run, Ordering::AcqRel);
+
+        let mut ctx = SystemContext {
+            world,
+            system: self,
+            storage: world.storage.read().expect("Could not read lock storage"),
+            ticks: Ticks::new(last_run, this_run),
+        };
+        (self.func)(&mut ctx);
+
+        debug!(
+            "System::run() - {} in {} micros",
+            self.name,
+            now.elapsed().as_micros().to_string()
+        );
+    }
+}
+
+/// What a system gets to see of the world while it runs.
+///
+/// Queries and storage access are checked against the system's declared access, so that
+/// systems the `Schedule` runs in parallel never touch the same data.
+pub struct SystemContext<'w> {
+    world: &'w World,
+    system: &'w System,
+    storage: RwLockReadGuard<'w, Storage>,
+    ticks: Ticks,
+}
+
+impl<'w> SystemContext<'w> {
+    /// Same as `World::query()`, restricted to the component types the system declared.
+    pub fn query<Q: Query>(&mut self) -> Vec<Q::Item<'_>> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// Same as `World::par_query()`, restricted to the component types the system declared.
+    pub fn par_query<Q: Query>(&mut self) -> impl ParallelIterator<Item = Q::Item<'_>> + '_ {
+        self.par_query_filtered::<Q, ()>()
+    }
+
+    /// Same as `World::query_filtered()`, except that `Added<T>` and `Changed<T>` look at
+    /// everything that happened since this system last ran.
+    pub fn query_filtered<Q: Query, F: Filter>(&mut self) -> Vec<Q::Item<'_>> {
+        self.validate_query::<Q, F>();
+
+        // SAFETY: the schedule never runs systems with conflicting access at the same time, and
+        // `&mut self` keeps the system from holding two queries at once.
+        unsafe { self.world.query_unchecked::<Q, F>(self.ticks) }
+    }
+
+    /// Parallel version of `query_filtered()`.
+    pub fn par_query_filtered<Q: Query, F: Filter>(
+        &mut self,
+    ) -> impl ParallelIterator<Item = Q::Item<'_>> + '_ {
+        self.validate_query::<Q, F>();
+
+        // SAFETY: see `query_filtered()`.
+        unsafe { self.world.par_query_unchecked::<Q, F>(self.ticks) }
+    }
+
+    /// The window `Added<T>` and `Changed<T>` look at, from this system's previous run to now.
+    pub fn ticks(&self) -> Ticks {
+        self.ticks
+    }
+
+    /// The storage, for buckets and resources that never change. Use `read_bucket()`,
+    /// `write_bucket()`, `resource()` and `write_resource()` for the ones the system declared,
+    /// and `commands()` to add new ones.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Read locks a bucket the system declared with `reads_bucket()` or `writes_bucket()`.
+    pub fn read_bucket<T: 'static>(&self, label: &'static str) -> BucketRef<'_, T> {
+        let access = &self.system.access;
+        if !access.bucket_reads().contains(&label) && !access.bucket_writes().contains(&label) {
+            panic!(
+                "System {} reads bucket {} without declaring it",
+                self.system.name, label
+            );
+        }
+
+        self.storage
+            .try_query_storage::<T>(label)
+            .unwrap_or_else(|err| panic!("System {} - {}", self.system.name, err))
+    }
+
+    /// Write locks a bucket the system declared with `writes_bucket()`.
+    pub fn write_bucket<T: 'static>(&self, label: &'static str) -> BucketMut<'_, T> {
+        if !self.system.access.bucket_writes().contains(&label) {
+            panic!(
+                "System {} writes bucket {} without declaring it",
+                self.system.name, label
+            );
+        }
+
+        self.storage
+            .try_write_storage::<T>(label)
+            .unwrap_or_else(|err| panic!("System {} - {}", self.system.name, err))
+    }
+
+    /// Read locks a resource the system declared with `reads_resource()` or `writes_resource()`.
+    pub fn resource<T: 'static>(&self) -> BucketRef<'_, T> {
+        let access = &self.system.access;
+        let declares = |list: &[(TypeId, &'static str)]| {
+            list.iter()
+                .any(|(type_id, _)| *type_id == TypeId::of::<T>())
+        };
+        if !declares(access.resource_reads()) && !declares(access.resource_writes()) {
+            panic!(
+                "System {} reads resource {} without declaring it",
+                self.system.name,
+                type_name::<T>()
+            );
+        }
+
+        self.storage.resource::<T>().unwrap_or_else(|| {
+            panic!(
+                "System {} - no resource {}",
+                self.system.name,
+                type_name::<T>()
+            )
+        })
+    }
+
+    /// Write locks a resource the system declared with `writes_resource()`.
+    pub fn write_resource<T: 'static>(&self) -> BucketMut<'_, T> {
+        let declared = self
+            .system
+            .access
+            .resource_writes()
+            .iter()
+            .any(|(type_id, _)| *type_id == TypeId::of::<T>());
+        if !declared {
+            panic!(
+                "System {} writes resource {} without declaring it",
+                self.system.name,
+                type_name::<T>()
+            );
+        }
+
+        self.storage.write_resource::<T>().unwrap_or_else(|| {
+            panic!(
+                "System {} - no resource {}",
+                self.system.name,
+                type_name::<T>()
+            )
+        })
+    }
+
+    pub fn commands(&self) -> &'w Commands {
+        &self.world.commands
+    }
+
+    pub fn input(&self) -> &'w InputHandler {
+        &self.world.input
+    }
+
+    pub fn stats(&self) -> &'w RwLock<Stats> {
+        &self.world.stats
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.world.is_alive(entity)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.system.name
+    }
+
+    fn validate_query<Q: Query, F: Filter>(&self) {
+        query::validate_access::<Q>();
+
+        let mut access = Access::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+
+        if let Some(name) = access.find_undeclared(&self.system.access) {
+            panic!(
+                "System {} queries {} without declaring it",
+                self.system.name, name
+            );
+        }
+    }
+}
+
+/// # Schedule
+///
+/// Orders the world's systems into batches. All systems in a batch have compatible access and
+/// run in parallel; batches run one after another.
+///
+/// A system ends up in a later batch than every system it has to run after, either because of
+/// an explicit `before()` / `after()` or because it conflicts with a system added before it.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<System>,
+    batches: Option<Vec<Vec<usize>>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system(&mut self, system: System) {
+        if self.systems.iter().any(|other| other.name == system.name) {
+            warn!(
+                "Schedule::add_system() - a system named {} already exists",
+                system.name
+            );
+        }
+
+        self.systems.push(system);
+        self.batches = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    /// Returns the names of the systems in every batch, building the batches if needed.
+    pub fn batches(&mut self) -> Vec<Vec<&'static str>> {
+        self.build();
+
+        self.batches
+            .as_ref()
+            .expect("Schedule batches were not built")
+            .iter()
+            .map(|batch| batch.iter().map(|&i| self.systems[i].name).collect())
+            .collect()
+    }
+
+    /// Computes the batches, unless they are still valid from a previous call.
+    ///
+    /// Panics if the `before()` / `after()` constraints form a cycle.
+    pub fn build(&mut self) {
+        if self.batches.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        let count = self.systems.len();
+
+        let mut labels: HashMap<&'static str, Vec<usize>> = HashMap::new();
+        for (i, system) in self.systems.iter().enumerate() {
+            for label in system.labels.iter() {
+                labels.entry(label).or_default().push(i);
+            }
+        }
+
+        let lookup = |system: &System, label: &'static str| -> Vec<usize> {
+            match labels.get(label) {
+                Some(indices) => indices.clone(),
+                None => {
+                    warn!(
+                        "Schedule::build() - system {} is ordered against unknown label {}",
+                        system.name, label
+                    );
+                    Vec::new()
+                },
+            }
+        };
+
+        // predecessors[i] holds every system that must finish before system i starts.
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for (i, system) in self.systems.iter().enumerate() {
+            for label in system.after.iter() {
+                predecessors[i].extend(lookup(system, label).into_iter().filter(|&j| j != i));
+            }
+            for label in system.before.iter() {
+                for j in lookup(system, label).into_iter().filter(|&j| j != i) {
+                    predecessors[j].push(i);
+                }
+            }
+        }
+
+        // Kahn's algorithm, always picking the earliest added system that is ready so that
+        // unconstrained systems keep their registration order.
+        let mut remaining: Vec<usize> = predecessors.iter().map(|p| p.len()).collect();
+        let mut done = vec![false; count];
+        let mut order = Vec::with_capacity(count);
+
+        while order.len() < count {
+            let next = (0..count).find(|&i| !done[i] && remaining[i] == 0);
+
+            let i = match next {
+                Some(i) => i,
+                None => {
+                    let cycle = (0..count)
+                        .filter(|&i| !done[i])
+                        .map(|i| self.systems[i].name)
+                        .collect::<Vec<_>>();
+                    panic!(
+                        "Schedule::build() - systems have cyclic ordering constraints: {:?}",
+                        cycle
+                    );
+                },
+            };
+
+            done[i] = true;
+            order.push(i);
+
+            for (j, preds) in predecessors.iter().enumerate() {
+                remaining[j] -= preds.iter().filter(|&&p| p == i).count();
+            }
+        }
+
+        let mut levels = vec![0; count];
+        for (position, &i) in order.iter().enumerate() {
+            let explicit = predecessors[i].iter().map(|&p| levels[p] + 1);
+            let conflicting = order[..position]
+                .iter()
+                .filter(|&&j| {
+                    !self.systems[j]
+                        .access
+                        .is_compatible(&self.systems[i].access)
+                })
+                .map(|&j| levels[j] + 1);
+
+            levels[i] = explicit.chain(conflicting).max().unwrap_or(0);
+        }
+
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for &i in order.iter() {
+            if batches.len() <= levels[i] {
+                batches.resize(levels[i] + 1, Vec::new());
+            }
+            batches[levels[i]].push(i);
+        }
+
+        debug!(
+            "Schedule::build() - {} systems in {} batches in {} micros",
+            count,
+            batches.len(),
+            now.elapsed().as_micros().to_string()
+        );
+
+        self.batches = Some(batches);
+    }
+
+    /// Runs every system, batch by batch. `build()` must have been called since the last
+    /// system was added.
+    pub fn run(&self, world: &World) {
+        let batches = self
+            .batches
+            .as_ref()
+            .expect("Schedule::run() called before Schedule::build()");
+
+        for batch in batches.iter() {
+            if batch.len() == 1 {
+                self.systems[batch[0]].run(world);
+            } else {
+                batch.par_iter().for_each(|&i| self.systems[i].run(world));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, NoPixelMap, Position, Velocity};
+
+    struct Score(u32);
+
+    fn system(name: &'static str) -> System {
+        System::new(name, |_| {})
+    }
+
+    #[test]
+    fn conflicting_systems_go_in_later_batches() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("write position").writes::<Position>());
+        schedule.add_system(system("read position").reads::<Position>());
+        schedule.add_system(system("read velocity").reads::<Velocity>());
+        schedule.add_system(system("write velocity").writes::<Velocity>());
+        schedule.add_system(system("read both").reads::<Position>().reads::<Velocity>());
+
+        assert_eq!(
+            schedule.batches(),
+            vec![
+                vec!["write position", "read velocity"],
+                vec!["read position", "write velocity"],
+                vec!["read both"],
+            ]
+        );
+    }
+
+    #[test]
+    fn readers_share_a_batch() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("first").reads::<Position>().reads_bucket("grid"));
+        schedule.add_system(system("second").reads::<Position>().reads_bucket("grid"));
+        schedule.add_system(system("third").writes_bucket("grid"));
+
+        assert_eq!(
+            schedule.batches(),
+            vec![vec!["first", "second"], vec!["third"]]
+        );
+    }
+
+    #[test]
+    fn resource_writers_go_in_later_batches() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("first").reads_resource::<Score>());
+        schedule.add_system(system("second").reads_resource::<Score>());
+        schedule.add_system(system("third").writes_resource::<Score>());
+        schedule.add_system(system("unrelated").writes_resource::<Position>());
+
+        assert_eq!(
+            schedule.batches(),
+            vec![vec!["first", "second", "unrelated"], vec!["third"]]
+        );
+    }
+
+    #[test]
+    fn systems_lock_declared_resources() {
+        let mut world = testing::world();
+        world
+            .storage
+            .get_mut()
+            .expect("Could not lock storage")
+            .insert_resource(Score(0));
+
+        world.add_system(
+            System::new("score", |ctx| ctx.write_resource::<Score>().0 += 1)
+                .writes_resource::<Score>(),
+        );
+        world.run::<NoPixelMap>();
+        world.run::<NoPixelMap>();
+
+        let storage = world.storage.read().expect("Could not read lock storage");
+        assert_eq!(storage.resource::<Score>().map(|score| score.0), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "without declaring it")]
+    fn undeclared_resource_write_panics() {
+        let mut world = testing::world();
+        world
+            .storage
+            .get_mut()
+            .expect("Could not lock storage")
+            .insert_resource(Score(0));
+
+        world.add_system(
+            System::new("score", |ctx| ctx.write_resource::<Score>().0 += 1)
+                .reads_resource::<Score>(),
+        );
+        world.run::<NoPixelMap>();
+    }
+
+    #[test]
+    fn explicit_order_is_kept() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("render").after("simulation"));
+        schedule.add_system(system("simulation").label("simulation"));
+        schedule.add_system(system("input").before("simulation"));
+
+        assert_eq!(
+            schedule.batches(),
+            vec![vec!["input"], vec!["simulation"], vec!["render"]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cyclic")]
+    fn cyclic_order_panics() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("a").after("b"));
+        schedule.add_system(system("b").after("a"));
+
+        schedule.build();
+    }
+}
@@ -0,0 +1,44 @@
+//! Helpers shared by the unit tests of this crate.
+
+use pixpox_common::Camera;
+use pixpox_utils::InputHandler;
+use serde::{Deserialize, Serialize};
+
+use crate::{Component, GlobalPixelMap, World};
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position(pub i32, pub i32);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Velocity(pub i32, pub i32);
+
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Name(pub String);
+
+/// `World::run()` needs a pixel map, this one does nothing.
+pub struct NoPixelMap;
+
+impl GlobalPixelMap for NoPixelMap {
+    fn render(&self, _pixels: &mut [u8]) {}
+
+    fn update(&mut self, _input: &InputHandler) {}
+
+    fn size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn get_camera(&self) -> Camera {
+        Camera::new(0, 0, 1, 1, 1, 1)
+    }
+}
+
+/// A world that can `run::<NoPixelMap>()`.
+pub fn world() -> World {
+    let mut world = World::new();
+    world
+        .storage
+        .get_mut()
+        .expect("Could not lock storage")
+        .new_bucket::<NoPixelMap>("pixelmap", NoPixelMap);
+    world
+}
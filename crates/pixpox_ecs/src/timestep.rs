@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+pub const DEFAULT_MAX_TICKS_PER_FRAME: u32 = 5;
+
+/// # FixedTimestep
+///
+/// Decides how many simulation ticks to run for each rendered frame, so the simulation runs at
+/// the same speed no matter how fast frames are presented.
+///
+/// Frame time, multiplied by the time scale, is added to an accumulator, and one tick is run
+/// for every whole timestep in it. What is left over is exposed as `alpha()`, which rendering
+/// can use to interpolate between the last two ticks.
+///
+/// When frames take too long to keep up, at most `max_ticks_per_frame()` ticks are run per
+/// frame and the rest of the backlog is dropped, so the simulation slows down instead of
+/// falling further and further behind.
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut clock = FixedTimestep::new(30.0);
+/// clock.set_time_scale(0.5); // slow motion, 15 ticks per second
+///
+/// for _ in 0..clock.advance(frame_time) {
+///     world.run::<GlobalPixelMap>();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    tick_rate: f64, // ticks per second
+    time_scale: f64,
+    max_ticks_per_frame: u32,
+    accumulator: Duration,
+    paused: bool,
+    pending_steps: u32, // single steps requested while paused
+    tick_count: u64,
+}
+
+impl FixedTimestep {
+    /// Panics if `tick_rate` is not a positive number.
+    pub fn new(tick_rate: f64) -> Self {
+        let mut clock = Self {
+            tick_rate: DEFAULT_TICK_RATE,
+            time_scale: 1.0,
+            max_ticks_per_frame: DEFAULT_MAX_TICKS_PER_FRAME,
+            accumulator: Duration::ZERO,
+            paused: false,
+            pending_steps: 0,
+            tick_count: 0,
+        };
+        clock.set_tick_rate(tick_rate);
+
+        clock
+    }
+
+    pub fn tick_rate(&self) -> f64 {
+        self.tick_rate
+    }
+
+    /// Panics if `tick_rate` is not a positive number.
+    pub fn set_tick_rate(&mut self, tick_rate: f64) {
+        assert!(
+            tick_rate.is_finite() && tick_rate > 0.0,
+            "Tick rate must be a positive number, got {}",
+            tick_rate
+        );
+
+        self.tick_rate = tick_rate;
+    }
+
+    /// Simulated time per tick.
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate)
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Speeds the simulation up (above 1.0) or slows it down (below 1.0) without changing the
+    /// timestep. Negative values are clamped to 0.0, which stops the simulation.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn max_ticks_per_frame(&self) -> u32 {
+        self.max_ticks_per_frame
+    }
+
+    /// Caps how many ticks a single frame may run to catch up. Clamped to at least 1.
+    pub fn set_max_ticks_per_frame(&mut self, max_ticks_per_frame: u32) {
+        self.max_ticks_per_frame = max_ticks_per_frame.max(1);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_paused(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Queues one tick for the next `advance()`. Does nothing unless the clock is paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    /// Number of ticks run since the clock was created.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// How far the accumulator is into the next tick, from 0.0 up to 1.0. Rendering can blend
+    /// the previous and current tick's state by this much to hide the fixed timestep.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() * self.tick_rate) as f32
+    }
+
+    /// Adds a frame's worth of time and returns how many ticks to run for it.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        if self.paused {
+            let ticks = self.pending_steps.min(self.max_ticks_per_frame);
+            self.pending_steps -= ticks;
+            self.tick_count += ticks as u64;
+
+            return ticks;
+        }
+
+        self.accumulator += frame_time.mul_f64(self.time_scale);
+
+        let timestep = self.timestep();
+        let mut ticks = 0;
+        while self.accumulator >= timestep && ticks < self.max_ticks_per_frame {
+            self.accumulator -= timestep;
+            ticks += 1;
+        }
+
+        // Drop the backlog we could not catch up on, but keep the fraction of a tick so alpha
+        // stays continuous.
+        if self.accumulator >= timestep {
+            self.accumulator = Duration::from_secs_f64(
+                self.accumulator.as_secs_f64() % timestep.as_secs_f64(),
+            );
+        }
+
+        self.tick_count += ticks as u64;
+
+        ticks
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_alpha(clock: &FixedTimestep, expected: f32) {
+        assert!(
+            (clock.alpha() - expected).abs() < 1e-4,
+            "alpha is {}, expected {}",
+            clock.alpha(),
+            expected
+        );
+    }
+
+    #[test]
+    fn accumulator_carries_over() {
+        let mut clock = FixedTimestep::new(10.0);
+
+        assert_eq!(clock.advance(ms(40)), 0);
+        assert_alpha(&clock, 0.4);
+
+        assert_eq!(clock.advance(ms(70)), 1);
+        assert_alpha(&clock, 0.1);
+
+        assert_eq!(clock.advance(ms(290)), 3);
+        assert_alpha(&clock, 0.0);
+        assert_eq!(clock.tick_count(), 4);
+    }
+
+    #[test]
+    fn backlog_above_the_cap_is_dropped() {
+        let mut clock = FixedTimestep::new(10.0);
+        clock.set_max_ticks_per_frame(3);
+
+        assert_eq!(clock.advance(ms(1050)), 3);
+        // Only the fraction of a tick is kept
+        assert_alpha(&clock, 0.5);
+        assert_eq!(clock.advance(ms(0)), 0);
+        assert_eq!(clock.advance(ms(50)), 1);
+
+        clock.set_max_ticks_per_frame(0);
+        assert_eq!(clock.max_ticks_per_frame(), 1);
+        assert_eq!(clock.advance(ms(300)), 1);
+    }
+
+    #[test]
+    fn time_scale_stretches_frame_time() {
+        let mut clock = FixedTimestep::new(10.0);
+
+        clock.set_time_scale(0.5);
+        assert_eq!(clock.advance(ms(100)), 0);
+        assert_eq!(clock.advance(ms(100)), 1);
+
+        clock.set_time_scale(2.0);
+        assert_eq!(clock.advance(ms(100)), 2);
+
+        clock.set_time_scale(-1.0);
+        assert_eq!(clock.time_scale(), 0.0);
+        assert_eq!(clock.advance(ms(1000)), 0);
+    }
+
+    #[test]
+    fn steps_only_run_while_paused() {
+        let mut clock = FixedTimestep::new(10.0);
+        clock.set_max_ticks_per_frame(2);
+
+        clock.step();
+        assert_eq!(clock.advance(ms(0)), 0);
+
+        clock.pause();
+        assert_eq!(clock.advance(ms(1000)), 0);
+
+        clock.step();
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(ms(0)), 2);
+        assert_eq!(clock.advance(ms(0)), 1);
+        assert_eq!(clock.advance(ms(0)), 0);
+        assert_eq!(clock.tick_count(), 3);
+
+        // Steps still pending when the clock resumes are dropped
+        clock.step();
+        clock.toggle_paused();
+        assert!(!clock.is_paused());
+        assert_eq!(clock.advance(ms(0)), 0);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut clock = FixedTimestep::new(60.0);
+
+        for frame in 0..1000 {
+            clock.advance(ms(frame % 50));
+            assert!(
+                (0.0..1.0).contains(&clock.alpha()),
+                "alpha is {} after frame {}",
+                clock.alpha(),
+                frame
+            );
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::Entity,
+    hierarchy::{Children, Parent},
+    system::{Stage, System},
+    Component,
+};
+
+/// Name of the system returned by `propagate_transforms()`, to order other systems against.
+pub const PROPAGATE_TRANSFORMS: &str = "propagate_transforms";
+
+/// # Transform2D
+///
+/// Position, rotation (in radians) and scale of an entity relative to its `Parent`, or to the
+/// world if it has none. Entities that should be drawn at their resulting place in the world
+/// also need a `GlobalTransform2D`, which `propagate_transforms()` keeps up to date.
+///
+/// The scale is applied along the entity's own axes, before its rotation. A rotated child of a
+/// parent with a non-uniform scale therefore ends up sheared, which a `Transform2D` can not
+/// hold, so globals are kept as an `Affine2` instead.
+///
+/// ## Example
+///
+/// ```ignore
+/// world.add_system(propagate_transforms());
+///
+/// let player = world.spawn_with((Transform2D::from_translation((40.0, 20.0)), GlobalTransform2D::default()));
+/// let tool = world.spawn_with((Transform2D::from_translation((2.0, 0.0)), GlobalTransform2D::default()));
+/// world.set_parent(tool, player);
+///
+/// // after the next tick, the tool's GlobalTransform2D is at (42.0, 20.0)
+/// ```
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transform2D {
+    pub translation: (f32, f32),
+    pub rotation: f32,
+    pub scale: (f32, f32),
+}
+
+impl Transform2D {
+    pub const IDENTITY: Self = Self {
+        translation: (0.0, 0.0),
+        rotation: 0.0,
+        scale: (1.0, 1.0),
+    };
+
+    pub fn from_translation(translation: (f32, f32)) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: (f32, f32)) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Maps a point from this transform's local space into its parent's space: scaled, then
+    /// rotated, then translated.
+    pub fn transform_point(&self, point: (f32, f32)) -> (f32, f32) {
+        let (x, y) = (point.0 * self.scale.0, point.1 * self.scale.1);
+        let (sin, cos) = self.rotation.sin_cos();
+
+        (
+            x * cos - y * sin + self.translation.0,
+            x * sin + y * cos + self.translation.1,
+        )
+    }
+
+    /// The same transform as a matrix, to compose with others.
+    pub fn to_affine(&self) -> Affine2 {
+        let (sin, cos) = self.rotation.sin_cos();
+
+        Affine2 {
+            x_axis: (cos * self.scale.0, sin * self.scale.0),
+            y_axis: (-sin * self.scale.1, cos * self.scale.1),
+            translation: self.translation,
+        }
+    }
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A 2D affine transform: a 2x2 matrix, given by where it maps the x and y axes, followed by a
+/// translation. Unlike a `Transform2D` it can hold any combination of transforms, including the
+/// shear of a rotated child under a non-uniformly scaled parent.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Affine2 {
+    pub x_axis: (f32, f32),
+    pub y_axis: (f32, f32),
+    pub translation: (f32, f32),
+}
+
+impl Affine2 {
+    pub const IDENTITY: Self = Self {
+        x_axis: (1.0, 0.0),
+        y_axis: (0.0, 1.0),
+        translation: (0.0, 0.0),
+    };
+
+    /// Maps a point, which is moved by the translation.
+    pub fn transform_point(&self, point: (f32, f32)) -> (f32, f32) {
+        let (x, y) = self.transform_vector(point);
+        (x + self.translation.0, y + self.translation.1)
+    }
+
+    /// Maps a direction or offset, which is not moved by the translation.
+    pub fn transform_vector(&self, vector: (f32, f32)) -> (f32, f32) {
+        (
+            self.x_axis.0 * vector.0 + self.y_axis.0 * vector.1,
+            self.x_axis.1 * vector.0 + self.y_axis.1 * vector.1,
+        )
+    }
+
+    /// The transform that applies `child` first and then `self`, i.e. where something placed
+    /// by `child` ends up when `child` is relative to `self`.
+    pub fn mul_affine(&self, child: &Affine2) -> Affine2 {
+        Affine2 {
+            x_axis: self.transform_vector(child.x_axis),
+            y_axis: self.transform_vector(child.y_axis),
+            translation: self.transform_point(child.translation),
+        }
+    }
+
+    /// Angle of the mapped x axis, in radians.
+    pub fn rotation(&self) -> f32 {
+        self.x_axis.1.atan2(self.x_axis.0)
+    }
+
+    /// Length of the mapped x axis, and the scale along y that gives the same area, negative if
+    /// the transform mirrors. Exact for any `Transform2D`, only an approximation once sheared.
+    pub fn scale(&self) -> (f32, f32) {
+        let determinant = self.x_axis.0 * self.y_axis.1 - self.y_axis.0 * self.x_axis.1;
+        let scale_x = self.x_axis.0.hypot(self.x_axis.1);
+
+        (scale_x, determinant / scale_x)
+    }
+}
+
+impl Default for Affine2 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<Transform2D> for Affine2 {
+    fn from(transform: Transform2D) -> Self {
+        transform.to_affine()
+    }
+}
+
+/// Where an entity's `Transform2D` ends up in the world, after those of all its ancestors are
+/// applied. Written by `propagate_transforms()`, and only meant to be read.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GlobalTransform2D(pub Affine2);
+
+impl GlobalTransform2D {
+    pub fn translation(&self) -> (f32, f32) {
+        self.0.translation
+    }
+
+    /// See `Affine2::rotation()`.
+    pub fn rotation(&self) -> f32 {
+        self.0.rotation()
+    }
+
+    /// See `Affine2::scale()`.
+    pub fn scale(&self) -> (f32, f32) {
+        self.0.scale()
+    }
+
+    /// Maps a point from the entity's local space into world space.
+    pub fn transform_point(&self, point: (f32, f32)) -> (f32, f32) {
+        self.0.transform_point(point)
+    }
+}
+
+/// A system that writes the `GlobalTransform2D` of every entity with a `Transform2D`, walking
+/// down from the entities without a `Parent`. An entity whose parent has no `Transform2D` is
+/// treated as a root. Only globals that actually moved are marked changed.
+///
+/// Runs in `Stage::PostUpdate`, so it sees every change made to transforms during the tick.
+pub fn propagate_transforms() -> System {
+    System::new(PROPAGATE_TRANSFORMS, |ctx| {
+        let nodes: HashMap<Entity, (Transform2D, Option<Entity>, Vec<Entity>)> = ctx
+            .query::<(&Transform2D, Option<&Parent>, Option<&Children>)>()
+            .into_iter()
+            .map(|(entity, local, parent, children)| {
+                let children = children
+                    .map(|children| children.to_vec())
+                    .unwrap_or_default();
+                (entity, (*local, parent.map(Parent::get), children))
+            })
+            .collect();
+
+        let mut globals = HashMap::with_capacity(nodes.len());
+        let mut stack: Vec<(Entity, Affine2)> = nodes
+            .iter()
+            .filter(|(_, (_, parent, _))| !parent.is_some_and(|parent| nodes.contains_key(&parent)))
+            .map(|(entity, (local, _, _))| (*entity, local.to_affine()))
+            .collect();
+
+        while let Some((entity, global)) = stack.pop() {
+            let (_, _, children) = &nodes[&entity];
+            for child in children {
+                if let Some((local, _, _)) = nodes.get(child) {
+                    stack.push((*child, global.mul_affine(&local.to_affine())));
+                }
+            }
+
+            globals.insert(entity, global);
+        }
+
+        for (entity, mut global) in ctx.query::<&mut GlobalTransform2D>() {
+            if let Some(new) = globals.get(&entity) {
+                if global.0 != *new {
+                    global.0 = *new;
+                }
+            }
+        }
+    })
+    .in_stage(Stage::PostUpdate)
+    .reads::<Transform2D>()
+    .reads::<Parent>()
+    .reads::<Children>()
+    .writes::<GlobalTransform2D>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::testing::{self, NoPixelMap};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < EPSILON && (actual.1 - expected.1).abs() < EPSILON,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn child_follows_parent() {
+        let mut world = testing::world();
+        world.add_system(propagate_transforms());
+
+        let player = world.spawn_with((
+            Transform2D::from_translation((40.0, 20.0)),
+            GlobalTransform2D::default(),
+        ));
+        let tool = world.spawn_with((
+            Transform2D::from_translation((2.0, 0.0)),
+            GlobalTransform2D::default(),
+        ));
+        world.set_parent(tool, player);
+
+        world.run::<NoPixelMap>();
+
+        let global = world.get_component::<GlobalTransform2D>(tool).unwrap();
+        assert_near(global.translation(), (42.0, 20.0));
+        assert_near(global.scale(), (1.0, 1.0));
+        assert!(global.rotation().abs() < EPSILON);
+    }
+
+    #[test]
+    fn composes_translation_rotation_and_uniform_scale() {
+        let parent = Transform2D::from_translation((10.0, 0.0))
+            .with_rotation(FRAC_PI_2)
+            .with_scale((2.0, 2.0));
+        let child = Transform2D::from_translation((1.0, 0.0)).with_rotation(0.25);
+
+        let global = parent.to_affine().mul_affine(&child.to_affine());
+
+        assert_near(global.translation, (10.0, 2.0));
+        assert!((global.rotation() - (FRAC_PI_2 + 0.25)).abs() < EPSILON);
+        assert_near(global.scale(), (2.0, 2.0));
+        assert_near(
+            global.transform_point((1.0, 0.0)),
+            parent.transform_point(child.transform_point((1.0, 0.0))),
+        );
+    }
+
+    #[test]
+    fn rotated_child_of_non_uniform_parent_is_sheared() {
+        let parent = Transform2D::IDENTITY.with_scale((2.0, 1.0));
+        let child = Transform2D::IDENTITY.with_rotation(FRAC_PI_2 / 2.0);
+
+        let global = parent.to_affine().mul_affine(&child.to_affine());
+
+        // Every point lands where applying the child and then the parent puts it
+        for point in [(1.0, 0.0), (0.0, 1.0), (3.0, -2.0)] {
+            assert_near(
+                global.transform_point(point),
+                parent.transform_point(child.transform_point(point)),
+            );
+        }
+
+        // The child's axes are stretched along the parent's x, so they are no longer square
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(global.x_axis, (2.0 * half, half));
+        assert_near(global.y_axis, (-2.0 * half, half));
+    }
+}
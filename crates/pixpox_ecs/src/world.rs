@@ -3,10 +3,12 @@
 
 use core::panic;
 use std::{
-    any::{self, Any},
+    any::{self, Any, TypeId},
     borrow::BorrowMut,
     cell::{RefCell, RefMut},
     collections::HashMap,
+    fs,
+    path::Path,
     sync::{
         atomic::{AtomicU32, AtomicUsize, Ordering},
         Arc, Mutex, RwLock,
@@ -15,9 +17,13 @@ use std::{
     time::{self, Duration, Instant},
 };
 
-use log::{debug, error, info};
-use pixpox_utils::stats::Stats;
-use rayon::prelude::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+use log::{debug, error, info, warn};
+use pixpox_utils::{stats::Stats, Tracer};
+use serde::{de::DeserializeOwned, Serialize};
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, ParallelIterator,
+};
 use winit::event::{Event, VirtualKeyCode};
 use winit_input_helper::WinitInputHelper;
 
@@ -25,13 +31,22 @@ use crate::GlobalPixelMap as GlobalPixelMapTrait;
 use pixpox_utils::InputHandler;
 
 use crate::{
+    archetypes::{Archetypes, ComponentInfo, Components, EntityLocation},
+    bundle::Bundle,
+    change_detection::{ChangeTick, Mut, Ticks},
+    command::{self, Commands},
+    event::{self, Events},
     component::{self},
+    hierarchy::{Children, Parent},
+    hooks::{ComponentHooks, HookFn, Hooks},
     entity::{Entity, EntityManager},
-    Label, Run, Storage, Texture, Update,
+    query::{self, Filter, Query},
+    snapshot::{self, SnapshotError, SnapshotFormat, SnapshotRegistry},
+    system::{Schedule, Stage, System},
+    timestep::FixedTimestep,
+    Component, Label, Run, Storage, Texture, Update,
 };
 
-use ticktock::{Clock, Timer};
-
 static MAX_WORLD_ID: AtomicUsize = AtomicUsize::new(0);
 
 struct WorldId(usize);
@@ -48,49 +63,56 @@ impl WorldId {
     }
 }
 
-fn print_type_of<T>(_: &T) {
-    println!("{}", std::any::type_name::<T>())
-}
-
 pub enum BucketAction {
     GET,
     PUT,
 }
 
-// TODO: Add a field for tick speed
 pub struct World {
     id: WorldId,
-    pub entities: EntityManager,
-    pub component_vecs: Vec<Box<dyn ComponentVec>>,
+    pub(crate) entities: EntityManager, // locations must stay in sync with `archetypes`
+    pub(crate) archetypes: Archetypes,
+    pub components: Components,
     pub storage: RwLock<Storage>,
+    pub commands: Commands,
+    schedules: [Schedule; Stage::ALL.len()], // indexed by `Stage::index()`
+    change_tick: ChangeTick,
+    last_change_tick: u32,
+    event_updaters: Vec<fn(&mut Storage)>, // swap the buffers of every added event type
+    pub(crate) snapshot_registry: SnapshotRegistry,
     pub last_update: time::Instant,
     pub stats: RwLock<Stats>,
+    pub tracer: Arc<Tracer>, // shared, so the app can time its own phases into the same capture
     pub input: InputHandler,
-    paused: bool,
+    clock: FixedTimestep,
 }
 
 impl World {
     pub fn new() -> Self {
         let entities = EntityManager::new();
-        let component_vecs = Vec::new();
-
-        // show some fps measurements every 5 seconds
-        let fps = Timer::apply(|delta_t, prev_tick| (delta_t, *prev_tick), 0)
-            .every(time::Duration::from_secs(5))
-            .start(time::Instant::now());
+        let archetypes = Archetypes::new();
+        let components = Components::default();
 
-        print_type_of(&fps);
+        let change_tick = ChangeTick::new();
 
         Self {
             id: WorldId::new()
                 .expect("More PixPox worlds have been created than currently supported."),
             entities,
-            component_vecs,
+            archetypes,
+            components,
             last_update: time::Instant::now(),
-            storage: RwLock::new(Storage::new()),
+            storage: RwLock::new(Storage::with_change_tick(change_tick.clone())),
+            commands: Commands::new(),
+            schedules: Default::default(),
+            change_tick,
+            last_change_tick: 0,
+            event_updaters: Vec::new(),
+            snapshot_registry: SnapshotRegistry::default(),
             stats: RwLock::new(Stats::new()),
+            tracer: Arc::new(Tracer::new()),
             input: InputHandler::new(),
-            paused: false,
+            clock: FixedTimestep::default(),
         }
     }
 
@@ -98,106 +120,686 @@ impl World {
         self.new_entity()
     }
 
-    pub fn add_component_to_entity<
-        ComponentType: 'static + Label + Run + Update + Clone + Send + Sync,
-    >(
+    pub fn add_component_to_entity<ComponentType: Component>(
         &mut self,
         entity: Entity,
         mut component: ComponentType,
     ) {
         let now = Instant::now();
-        // Search for any existing ComponentVecs that match the type of the component being added.
-        for component_vec in self.component_vecs.iter_mut() {
-            if let Some(component_vec) = component_vec
-                .as_any_mut()
-                .downcast_mut::<Vec<Option<ComponentType>>>()
-            {
-                component_vec[entity.id as usize] = Some(component);
-
-                debug!(
-                    "World::add_component_to_entity() existing component_vec: {} micros",
-                    now.elapsed().as_micros().to_string()
-                );
 
+        let location = match self.entities.location(entity) {
+            Some(location) => location,
+            None => {
+                error!(
+                    "World::add_component_to_entity() - entity {} (generation {}) is not alive",
+                    entity.id, entity.generation
+                );
                 return;
-            }
-        }
+            },
+        };
+
+        let type_id = TypeId::of::<ComponentType>();
+        self.components.register_component(&mut component);
+        let label = self
+            .components
+            .get(type_id)
+            .expect("Components::register_component() did not register the type")
+            .label;
+
+        let archetype = self
+            .archetypes
+            .get_mut(location.archetype)
+            .expect("Entity location points to a missing archetype");
 
-        // No matching component storage exists yet, so we have to make one.
-        let mut new_component_vec: Vec<Option<ComponentType>> =
-            Vec::with_capacity(self.entities.living_entity_count);
+        let change_tick = self.change_tick.get();
 
-        // All existing entities don't have this component, so we give them `None`
-        for _ in 0..self.entities.living_entity_count {
-            new_component_vec.push(None);
+        // The entity already has a component of this type, replace it in place.
+        if archetype.contains(type_id) {
+            self.run_hook(entity, type_id, |hooks| hooks.on_replace);
+            self.archetypes
+                .get_mut(location.archetype)
+                .expect("Entity location points to a missing archetype")
+                .replace_component(location.row, component, change_tick);
+            self.run_hook(entity, type_id, |hooks| hooks.on_add);
+
+            debug!(
+                "World::add_component_to_entity() - Replaced component: {} on entity: {} in {} micros",
+                label,
+                entity.id,
+                now.elapsed().as_micros().to_string()
+            );
+
+            return;
         }
 
-        let label = component.label();
-        // Give this Entity the Component.
-        new_component_vec[entity.id as usize] = Some(component);
-        self.component_vecs.push(Box::new(new_component_vec));
+        // Otherwise move the entity to the archetype that also has this component type.
+        let target = self
+            .archetypes
+            .with_type(location.archetype, type_id, &self.components);
+        let row = self.move_entity(entity, location, target);
+
+        let archetype = self
+            .archetypes
+            .get_mut(target)
+            .expect("Archetypes::with_type() returned a missing archetype");
+        // SAFETY: the entity was just moved into `target`, which has one more column than its
+        // old archetype, and this is the component for it.
+        unsafe { archetype.push_component(component, change_tick) };
+        self.run_hook(entity, type_id, |hooks| hooks.on_add);
 
         debug!(
-            "World::add_component_to_entity() - Added component: {} to entity: {} in {} micros",
+            "World::add_component_to_entity() - Added component: {} to entity: {} (row {}) in {} micros",
             label,
             entity.id,
+            row,
             now.elapsed().as_micros().to_string()
         );
     }
 
-    pub fn query_components<T: 'static>(&mut self, entities: Vec<&Entity>) -> Option<Vec<&T>> {
+    /// Spawns an entity with every component of the bundle, moving it straight into the
+    /// bundle's archetype.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        self.spawn_batch(std::iter::once(bundle))[0]
+    }
+
+    /// Spawns one entity per bundle, all in a single pass: the bundle's archetype is looked up
+    /// once and its columns are reserved up front, instead of moving every entity through one
+    /// archetype per component. Returns the spawned entities, in order.
+    ///
+    /// Panics if the bundle holds the same component type twice.
+    pub fn spawn_batch<B: Bundle>(&mut self, bundles: impl IntoIterator<Item = B>) -> Vec<Entity> {
         let now = Instant::now();
 
-        for component_vec in self.component_vecs.iter_mut() {
-            if let Some(component_vec) = component_vec.as_any_mut().downcast_mut::<Vec<Option<T>>>()
-            {
-                debug!(
-                    "World::query_components() in {}",
-                    now.elapsed().as_micros().to_string()
-                );
+        let mut bundles = bundles.into_iter().peekable();
+        match bundles.peek_mut() {
+            Some(first) => first.register(&mut self.components),
+            None => return Vec::new(),
+        }
 
-                let res = entities
-                    .iter()
-                    .filter_map(|entity| {
-                        component_vec
-                            .get(entity.id)
-                            .expect("Entity could not be found in vec")
-                            .as_ref()
-                    })
-                    .collect::<Vec<&T>>();
-
-                return Some(res);
+        let types = B::type_ids();
+        let type_count = types.len();
+        let archetype_id = self.archetypes.get_or_insert(types, &self.components);
+
+        let archetype = self
+            .archetypes
+            .get_mut(archetype_id)
+            .expect("Archetypes::get_or_insert() returned a missing archetype");
+        assert_eq!(
+            archetype.types().len(),
+            type_count,
+            "World::spawn_batch() - bundle holds the same component type twice"
+        );
+
+        let (additional, _) = bundles.size_hint();
+        archetype.reserve(additional);
+        self.entities.reserve(additional);
+
+        let change_tick = self.change_tick.get();
+        let mut spawned = Vec::with_capacity(additional);
+
+        for bundle in bundles {
+            let entity = self.entities.create();
+            let row = archetype.push_entity(entity);
+            // SAFETY: the archetype holds exactly the bundle's component types, and the entity
+            // was just pushed.
+            unsafe { bundle.push(archetype, change_tick) };
+
+            self.entities.set_location(
+                entity.id,
+                EntityLocation {
+                    archetype: archetype_id,
+                    row,
+                },
+            );
+            spawned.push(entity);
+        }
+
+        let types = self
+            .archetypes
+            .get(archetype_id)
+            .expect("Archetypes::get_or_insert() returned a missing archetype")
+            .types()
+            .to_vec();
+        if self.components.any_hooks(&types) {
+            for entity in spawned.iter() {
+                for type_id in types.iter() {
+                    self.run_hook(*entity, *type_id, |hooks| hooks.on_add);
+                }
             }
         }
 
-        return None;
+        debug!(
+            "World::spawn_batch() - spawned {} entities in {} micros",
+            spawned.len(),
+            now.elapsed().as_micros().to_string()
+        );
+
+        spawned
+    }
+
+    /// Makes the world call `T`'s `ComponentHooks` whenever a `T` is added, replaced, removed
+    /// or despawned. Components already in the world are not affected until then.
+    pub fn register_hooks<T: ComponentHooks>(&mut self) {
+        info!("World::register_hooks() - {}", std::any::type_name::<T>());
+        self.components.set_hooks(TypeId::of::<T>(), Hooks::new::<T>());
+    }
+
+    /// Calls the hook picked by `hook` on the entity's component with the given type id, if
+    /// the type has hooks. The entity must be alive and have such a component.
+    fn run_hook(&mut self, entity: Entity, type_id: TypeId, hook: fn(&Hooks) -> HookFn) {
+        let hooks = match self.components.hooks(type_id) {
+            Some(hooks) => hooks,
+            None => return,
+        };
+
+        let location = self
+            .entities
+            .location(entity)
+            .expect("World::run_hook() - entity is not alive");
+        let (column, _) = self
+            .archetypes
+            .get_mut(location.archetype)
+            .and_then(|archetype| archetype.column_with_ticks_mut(type_id))
+            .expect("World::run_hook() - entity has no such component");
+        let storage = self.storage.get_mut().expect("Could not lock storage");
+
+        hook(&hooks)(column, location.row, entity, storage, &self.commands);
+    }
+
+    /// Registers a system to run every tick in its stage, or once for `Stage::Startup`. See
+    /// `Stage` for the order stages run in.
+    pub fn add_system(&mut self, system: System) {
+        info!(
+            "World::add_system() - {} in {:?}",
+            system.name(),
+            system.stage()
+        );
+        self.schedules[system.stage().index()].add_system(system);
+    }
+
+    /// Runs the systems of a stage, if it has any.
+    fn run_stage(&mut self, stage: Stage) {
+        if self.schedules[stage.index()].is_empty() {
+            return;
+        }
+
+        let tracer = Arc::clone(&self.tracer);
+        let _span = tracer.span("stage", stage.name());
+
+        let now = Instant::now();
+        self.schedules[stage.index()].build();
+        self.schedules[stage.index()].run(self);
+
+        self.stats
+            .write()
+            .expect("Could not write lock stats")
+            .update_sector(stage.systems_sector(), now.elapsed().as_secs_f32());
+    }
+
+    /// Adds an `Events<E>` channel to the storage, whose buffers are swapped at the start of
+    /// every tick. Adding the same event type twice does nothing.
+    pub fn add_event<E: 'static + Send + Sync>(&mut self) {
+        let storage = self.storage.get_mut().expect("Could not lock storage");
+
+        if storage.contains_resource::<Events<E>>() {
+            return;
+        }
+
+        storage.insert_resource(Events::<E>::new());
+        self.event_updaters.push(event::update_events::<E>);
+
+        info!("World::add_event() - {}", std::any::type_name::<E>());
+    }
+
+    /// Sends an event on the channel added with `add_event::<E>()`.
+    pub fn send_event<E: 'static + Send + Sync>(&self, event: E) {
+        self.storage
+            .read()
+            .expect("Could not read lock storage")
+            .events::<E>()
+            .expect("World::send_event() - event type was not added with World::add_event()")
+            .send(event);
+    }
+
+    pub fn query_components<T: 'static>(&mut self, entities: Vec<&Entity>) -> Option<Vec<&T>> {
+        let now = Instant::now();
+
+        if !self.components.contains(TypeId::of::<T>()) {
+            return None;
+        }
+
+        let res = entities
+            .iter()
+            .filter_map(|entity| self.entities.location(**entity))
+            .filter_map(|location| {
+                self.archetypes
+                    .get(location.archetype)
+                    .and_then(|archetype| archetype.components::<T>())
+                    .map(|components| &components[location.row])
+            })
+            .collect::<Vec<&T>>();
+
+        debug!(
+            "World::query_components() in {}",
+            now.elapsed().as_micros().to_string()
+        );
+
+        Some(res)
     }
 
     pub fn query_components_for_render<T: 'static + Texture>(&mut self) -> Option<Vec<&T>> {
         let now = Instant::now();
 
-        for component_vec in self.component_vecs.iter_mut() {
-            if let Some(component_vec) = component_vec.as_any_mut().downcast_mut::<Vec<Option<T>>>()
-            {
-                debug!(
-                    "World::query_entities_for_render() in {}",
-                    now.elapsed().as_micros().to_string()
-                );
+        if !self.components.contains(TypeId::of::<T>()) {
+            return None;
+        }
 
-                let res = component_vec
-                    .iter_mut()
-                    .filter_map(|x| x.as_ref())
-                    .collect::<Vec<&T>>();
+        let res = self
+            .archetypes
+            .iter()
+            .filter_map(|archetype| archetype.components::<T>())
+            .flatten()
+            .collect::<Vec<&T>>();
+
+        debug!(
+            "World::query_entities_for_render() in {}",
+            now.elapsed().as_micros().to_string()
+        );
+
+        Some(res)
+    }
+
+    /// Iterates over every living entity that has all the components in `Q`, e.g.
+    /// `world.query::<(&Position, &mut Velocity)>()` yields `(Entity, &Position, Mut<Velocity>)`.
+    ///
+    /// Panics if `Q` borrows a component type mutably more than once, or both mutably and
+    /// immutably.
+    pub fn query<Q: Query>(&mut self) -> Vec<Q::Item<'_>> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// Parallel version of `query()`, matched entities are fetched on the rayon thread pool.
+    pub fn par_query<Q: Query>(&mut self) -> impl ParallelIterator<Item = Q::Item<'_>> + '_ {
+        self.par_query_filtered::<Q, ()>()
+    }
 
-                return Some(res);
+    /// Same as `query()`, but only yields entities that match the filter `F`, e.g.
+    /// `world.query_filtered::<&Cell, Changed<Cell>>()`.
+    ///
+    /// `Added<T>` and `Changed<T>` look at everything that happened since the start of the
+    /// latest `run()`.
+    pub fn query_filtered<Q: Query, F: Filter>(&mut self) -> Vec<Q::Item<'_>> {
+        query::validate_access::<Q>();
+        let ticks = self.ticks();
+
+        // SAFETY: `&mut self` guarantees nothing else borrows the columns.
+        unsafe { self.query_unchecked::<Q, F>(ticks) }
+    }
+
+    /// Parallel version of `query_filtered()`.
+    pub fn par_query_filtered<Q: Query, F: Filter>(
+        &mut self,
+    ) -> impl ParallelIterator<Item = Q::Item<'_>> + '_ {
+        query::validate_access::<Q>();
+        let ticks = self.ticks();
+
+        // SAFETY: `&mut self` guarantees nothing else borrows the columns.
+        unsafe { self.par_query_unchecked::<Q, F>(ticks) }
+    }
+
+    /// # Safety
+    ///
+    /// `Q`'s access must have been validated, and no column `Q` writes to may be borrowed
+    /// anywhere else while the items are alive.
+    pub(crate) unsafe fn query_unchecked<Q: Query, F: Filter>(
+        &self,
+        ticks: Ticks,
+    ) -> Vec<Q::Item<'_>> {
+        let now = Instant::now();
+
+        let mut res = Vec::new();
+        for archetype in self.archetypes.iter() {
+            let (columns, filter) = match (Q::columns(archetype), F::state(archetype)) {
+                (Some(columns), Some(filter)) => (columns, filter),
+                _ => continue,
+            };
+
+            for (row, entity) in archetype.entities().iter().enumerate() {
+                if F::matches(filter, row, ticks) {
+                    res.push(Q::fetch(columns, *entity, row, ticks));
+                }
             }
         }
 
-        return None;
+        debug!(
+            "World::query() in {} micros",
+            now.elapsed().as_micros().to_string()
+        );
+
+        res
+    }
+
+    /// # Safety
+    ///
+    /// See `query_unchecked()`.
+    pub(crate) unsafe fn par_query_unchecked<Q: Query, F: Filter>(
+        &self,
+        ticks: Ticks,
+    ) -> impl ParallelIterator<Item = Q::Item<'_>> + '_ {
+        let matched = self
+            .archetypes
+            .iter()
+            .filter_map(|archetype| {
+                Some((
+                    Q::columns(archetype)?,
+                    F::state(archetype)?,
+                    archetype.entities(),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        matched
+            .into_par_iter()
+            .flat_map(move |(columns, filter, entities)| {
+                entities
+                    .par_iter()
+                    .enumerate()
+                    .filter(move |(row, _)| unsafe { F::matches(filter, *row, ticks) })
+                    .map(move |(row, entity)| unsafe { Q::fetch(columns, *entity, row, ticks) })
+            })
+    }
+
+    /// The world's entities and where each of them is stored.
+    pub fn entities(&self) -> &EntityManager {
+        &self.entities
+    }
+
+    /// The archetype tables holding every entity's components.
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
+    /// The current change tick. Everything added or written to is stamped with it.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick.get()
+    }
+
+    /// The change tick at the start of the latest `run()`.
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+
+    /// Bumps the change tick, returning the new value.
+    pub(crate) fn increment_change_tick(&self) -> u32 {
+        self.change_tick.increment()
+    }
+
+    fn ticks(&self) -> Ticks {
+        Ticks::new(self.last_change_tick, self.change_tick.get())
+    }
+
+    /// Removes the entity and every component it owns. Its slot is recycled by the next
+    /// `spawn()`, and the old handle is rejected from then on. Returns `false` if the entity
+    /// was already dead.
+    ///
+    /// The entity is detached from its parent, and its children become roots; use
+    /// `despawn_recursive()` to remove them too.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        let location = match self.entities.location(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+
+        let types = self
+            .archetypes
+            .get(location.archetype)
+            .expect("Entity location points to a missing archetype")
+            .types()
+            .to_vec();
+        if self.components.any_hooks(&types) {
+            for type_id in types {
+                self.run_hook(entity, type_id, |hooks| hooks.on_despawn);
+            }
+        }
+
+        // The entity's own `Parent` and `Children` go with it, but the entities on the other
+        // side of them have to be updated. That moves them between archetypes, which can move
+        // this entity as well.
+        if let Some(parent) = self.parent(entity) {
+            self.remove_child(parent, entity);
+        }
+        for child in self.children(entity).to_vec() {
+            self.remove_component::<Parent>(child);
+        }
+        let location = self
+            .entities
+            .location(entity)
+            .expect("World::despawn() - entity died while being despawned");
+
+        if let Some(swapped) = self.archetypes.remove(location) {
+            self.entities.set_location(swapped.id, location);
+        }
+
+        self.entities.destroy(entity);
+
+        debug!("World::despawn() - despawned entity: {}", entity.id);
+
+        true
+    }
+
+    /// Despawns the entity along with its children, their children, and so on. Returns `false`
+    /// if the entity was already dead.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        for child in self.children(entity).to_vec() {
+            self.despawn_recursive(child);
+        }
+
+        self.despawn(entity)
+    }
+
+    /// Attaches `child` to `parent`, detaching it from its previous parent first. Returns
+    /// `false`, and changes nothing, if either entity is dead or if `parent` is `child` itself
+    /// or one of its descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return false;
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == child {
+                warn!(
+                    "World::set_parent() - entity {} cannot be a child of its own descendant {}",
+                    child.id, parent.id
+                );
+                return false;
+            }
+            ancestor = self.parent(current);
+        }
+
+        if self.parent(child) == Some(parent) {
+            return true;
+        }
+
+        self.remove_parent(child);
+        self.add_component_to_entity(child, Parent(parent));
+
+        match self.get_component_mut::<Children>(parent) {
+            Some(mut children) => children.0.push(child),
+            None => self.add_component_to_entity(parent, Children(vec![child])),
+        }
+
+        true
+    }
+
+    /// Detaches `child` from its parent, making it a root. Returns the old parent, or `None`
+    /// if the entity is dead or had no parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let parent = self.remove_component::<Parent>(child)?.get();
+        self.remove_child(parent, child);
+
+        Some(parent)
+    }
+
+    /// Removes `child` from the parent's `Children`, and drops `Children` once it is empty.
+    fn remove_child(&mut self, parent: Entity, child: Entity) {
+        let now_empty = match self.get_component_mut::<Children>(parent) {
+            Some(mut children) => {
+                children.0.retain(|other| *other != child);
+                children.is_empty()
+            },
+            None => false,
+        };
+
+        if now_empty {
+            self.remove_component::<Children>(parent);
+        }
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get_component::<Parent>(entity).map(Parent::get)
+    }
+
+    /// The entity's children, in the order they were attached. Empty if it has none or is dead.
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        self.get_component::<Children>(entity)
+            .map_or(&[], |children| &children.0)
+    }
+
+    /// Removes the entity's component with the given type id, moving the entity to the
+    /// archetype without it. Returns `false` if the entity is dead or has no such component.
+    pub(crate) fn remove_component_by_id(&mut self, entity: Entity, type_id: TypeId) -> bool {
+        let location = match self.entities.location(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+
+        let has_component = self
+            .archetypes
+            .get(location.archetype)
+            .is_some_and(|archetype| archetype.contains(type_id));
+
+        if !has_component {
+            return false;
+        }
+
+        self.run_hook(entity, type_id, |hooks| hooks.on_remove);
+
+        let target = self
+            .archetypes
+            .without_type(location.archetype, type_id, &self.components);
+        self.move_entity(entity, location, target);
+
+        true
+    }
+
+    /// Removes the entity's component of type `T` and returns it, moving the entity to the
+    /// archetype without it. Returns `None` if the entity is dead or has no such component.
+    pub fn remove_component<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        let location = self.entities.location(entity)?;
+        let type_id = TypeId::of::<T>();
+
+        if !self.archetypes.get(location.archetype)?.contains(type_id) {
+            return None;
+        }
+
+        self.run_hook(entity, type_id, |hooks| hooks.on_remove);
+
+        let target = self
+            .archetypes
+            .without_type(location.archetype, type_id, &self.components);
+        let (component, row, swapped) = self.archetypes.move_entity_take::<T>(location, target);
+        self.set_moved_location(entity, location, target, row, swapped);
+
+        debug!(
+            "World::remove_component() - removed {} from entity: {}",
+            any::type_name::<T>(),
+            entity.id
+        );
+
+        Some(component)
+    }
+
+    /// Returns `true` if the entity is alive and has a component of type `T`.
+    pub fn has_component<T: 'static>(&self, entity: Entity) -> bool {
+        self.entities
+            .location(entity)
+            .and_then(|location| self.archetypes.get(location.archetype))
+            .is_some_and(|archetype| archetype.contains(TypeId::of::<T>()))
+    }
+
+    pub fn get_component<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        let location = self.entities.location(entity)?;
+
+        self.archetypes
+            .get(location.archetype)?
+            .components::<T>()?
+            .get(location.row)
+    }
+
+    /// Like `get_component()`, but mutable. Writing through the returned `Mut` marks the
+    /// component changed.
+    pub fn get_component_mut<T: 'static>(&mut self, entity: Entity) -> Option<Mut<'_, T>> {
+        let location = self.entities.location(entity)?;
+        let change_tick = self.change_tick.get();
+
+        let (component, ticks) = self
+            .archetypes
+            .get_mut(location.archetype)?
+            .get_with_ticks_mut::<T>(location.row)?;
+
+        Some(Mut::new(component, ticks, change_tick))
+    }
+
+    /// Labels of every component the entity has, as reported by their `Label` implementation
+    /// when they were first added. Returns `None` if the entity is dead.
+    pub fn entity_components(&self, entity: Entity) -> Option<Vec<&'static str>> {
+        let location = self.entities.location(entity)?;
+        let archetype = self.archetypes.get(location.archetype)?;
+
+        let labels = archetype
+            .types()
+            .iter()
+            .filter_map(|type_id| self.components.get(*type_id))
+            .map(|info| info.label)
+            .collect();
+
+        Some(labels)
+    }
+
+    /// Label of every registered component type, with the number of living entities that have
+    /// it, in registration order.
+    pub fn component_counts(&self) -> Vec<(&'static str, usize)> {
+        self.components
+            .iter()
+            .map(|info| {
+                let count = self
+                    .archetypes
+                    .iter()
+                    .filter(|archetype| archetype.contains(info.type_id))
+                    .map(|archetype| archetype.len())
+                    .sum();
+
+                (info.label, count)
+            })
+            .collect()
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn clock(&self) -> &FixedTimestep {
+        &self.clock
+    }
+
+    /// Tick rate, time scale, pausing and single-stepping are all set on the clock.
+    pub fn clock_mut(&mut self) -> &mut FixedTimestep {
+        &mut self.clock
     }
 
     pub fn toggle_paused(&mut self) {
-        self.paused = !self.paused;
+        self.clock.toggle_paused();
     }
 
     pub fn handle_input(&mut self) {
@@ -209,40 +811,157 @@ impl World {
          */
     }
 
-    pub fn run<T: 'static + GlobalPixelMapTrait>(&mut self) {
-        self.stats.write().expect("Could not write lock stats").new_tick();
+    /// Runs as many ticks as the clock says the time since the last call is worth, zero or
+    /// more. Call this once per frame. Returns the number of ticks run.
+    pub fn advance<T: 'static + GlobalPixelMapTrait>(&mut self) -> u32 {
+        let now = Instant::now();
+        let frame_time = now - self.last_update;
+        self.last_update = now;
 
-        if self.paused {
-            return;
+        self.advance_by::<T>(frame_time)
+    }
+
+    /// Like `advance()`, but with an explicit frame time instead of the time since the last
+    /// call, e.g. to replay a run deterministically.
+    pub fn advance_by<T: 'static + GlobalPixelMapTrait>(&mut self, frame_time: Duration) -> u32 {
+        self.stats.write().expect("Could not write lock stats").new_frame();
+
+        let tracer = Arc::clone(&self.tracer);
+        let _span = tracer.span("world", "World::advance");
+
+        // The key presses and clicks collected so far belong to the first tick to run, so a
+        // frame that runs no ticks leaves them for the next one.
+        let ticks = self.clock.advance(frame_time);
+        for _ in 0..ticks {
+            self.run::<T>();
+            self.input.clear_actions();
         }
 
+        ticks
+    }
+
+    /// Runs a single simulation tick right away, regardless of the clock, going through every
+    /// `Stage` in order.
+    pub fn run<T: 'static + GlobalPixelMapTrait>(&mut self) {
+        let tracer = Arc::clone(&self.tracer);
+        let _span = tracer.span("world", "World::run");
+        let tick_now = Instant::now();
+
         self.handle_input();
 
+        self.last_change_tick = self.change_tick.get();
+        self.increment_change_tick();
+
+        // Events sent two ticks ago have been seen by every reader by now.
+        let storage = self.storage.get_mut().expect("Could not lock storage");
+        for update in self.event_updaters.iter() {
+            update(storage);
+        }
+
+        // Startup systems added since the last tick run once, and are then dropped.
+        if !self.schedules[Stage::Startup.index()].is_empty() {
+            let _span = tracer.span("stage", Stage::Startup.name());
+            let mut startup = std::mem::take(&mut self.schedules[Stage::Startup.index()]);
+            startup.build();
+            startup.run(self);
+            self.apply_commands();
+        }
+
+        self.run_stage(Stage::PreUpdate);
+
+        // Systems leave the tick ahead of their own runs, so writes stamped with it here are seen
+        // by every system next tick, including the ones that already ran in this one.
+        let change_tick = self.change_tick.get();
+
+        // Every component type is timed on its own, so a slow tick can be traced to its label.
         let now = Instant::now();
-        for component_vec in self.component_vecs.iter_mut() {
-            component_vec.run_all(&mut self.storage);
+        for info in self.components.iter().filter(|info| info.runs()) {
+            let _span = tracer.span("run", info.label);
+            let component_now = Instant::now();
+            for archetype in self.archetypes.iter_mut() {
+                if let Some(column) = archetype.column_with_ticks_mut(info.type_id) {
+                    info.run(column, change_tick, &self.storage, &self.commands);
+                }
+            }
+            self.stats
+                .write()
+                .expect("Could not write lock stats")
+                .update_sector(&info.run_sector, component_now.elapsed().as_secs_f32());
         }
-        let elapsed = Instant::now() - now;
-        self.stats.write().expect("KUR").update_sector("run()".to_string(), elapsed.as_secs_f32());
+        self.stats
+            .write()
+            .expect("Could not write lock stats")
+            .update_sector("run()", now.elapsed().as_secs_f32());
+        self.run_stage(Stage::Run);
 
+        let change_tick = self.change_tick.get();
         let now = Instant::now();
-        for component_vec in self.component_vecs.iter_mut() {
-            component_vec.update_all(&mut self.storage, &mut self.input, &self.stats);
+        for info in self.components.iter().filter(|info| info.updates()) {
+            let _span = tracer.span("update", info.label);
+            let component_now = Instant::now();
+            for archetype in self.archetypes.iter_mut() {
+                if let Some(column) = archetype.column_with_ticks_mut(info.type_id) {
+                    info.update(
+                        column,
+                        change_tick,
+                        &self.storage,
+                        &self.input,
+                        &self.stats,
+                        &self.commands,
+                    );
+                }
+            }
+            self.stats
+                .write()
+                .expect("Could not write lock stats")
+                .update_sector(&info.update_sector, component_now.elapsed().as_secs_f32());
         }
-        let mut storage = self.storage.write().expect("Could not lock storage");
-        storage.update_global_pixel_map::<T>(&self.input);
+        self.stats
+            .write()
+            .expect("Could not write lock stats")
+            .update_sector("update()", now.elapsed().as_secs_f32());
+        self.run_stage(Stage::Update);
+
+        self.run_stage(Stage::PostUpdate);
+
+        // Sync point: structural changes queued so far take effect here, before rendering.
+        self.apply_commands();
 
-        let elapsed = Instant::now() - now;
-        self.stats.write().expect("KUR").update_sector("update()".to_string(), elapsed.as_secs_f32());
+        self.run_stage(Stage::PreRender);
+
+        {
+            let _span = tracer.span("world", "update_global_pixel_map()");
+            self.storage
+                .read()
+                .expect("Could not read lock storage")
+                .update_global_pixel_map::<T>(&self.input);
+        }
+
+        self.apply_commands();
+
+        let mut stats = self.stats.write().expect("Could not write lock stats");
+        for (info, (_, count)) in self.components.iter().zip(self.component_counts()) {
+            stats.update_value(&info.count_value, count as f32);
+        }
+        stats.new_tick(tick_now.elapsed().as_secs_f32());
+    }
+
+    /// Applies every command queued on `world.commands`, in the order they were queued.
+    pub fn apply_commands(&mut self) {
+        let tracer = Arc::clone(&self.tracer);
+        let _span = tracer.span("world", "apply_commands()");
+
+        let queue = self.commands.take();
+        command::apply(queue, self);
     }
 
     fn new_entity(&mut self) -> Entity {
         let entity = self.entities.create();
         let now = Instant::now();
 
-        for component_vec in self.component_vecs.iter_mut() {
-            component_vec.push_none();
-        }
+        // New entities have no components yet, so they start out in the empty archetype.
+        let location = self.archetypes.spawn(entity);
+        self.entities.set_location(entity.id, location);
 
         debug!(
             "World::new_entity(): {} micros",
@@ -252,9 +971,123 @@ impl World {
         return entity;
     }
 
-    fn spawn_random_terrain() {}
+    /// Moves the entity to the `target` archetype and patches the location of whichever entity
+    /// took its old row. Returns the entity's new row.
+    fn move_entity(&mut self, entity: Entity, location: EntityLocation, target: usize) -> usize {
+        let (row, swapped) = self.archetypes.move_entity(location, target);
+        self.set_moved_location(entity, location, target, row, swapped);
+
+        row
+    }
+
+    /// Records the entity's new place after a move, along with that of the entity that was
+    /// swapped into its old row.
+    fn set_moved_location(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+        target: usize,
+        row: usize,
+        swapped: Option<Entity>,
+    ) {
+        if let Some(swapped) = swapped {
+            self.entities.set_location(swapped.id, location);
+        }
+
+        self.entities.set_location(
+            entity.id,
+            EntityLocation {
+                archetype: target,
+                row,
+            },
+        );
+    }
+
+    /// Spawns the entity under exactly this id and generation. Returns `false` if the slot is
+    /// already taken.
+    pub(crate) fn spawn_at(&mut self, entity: Entity) -> bool {
+        if !self.entities.create_at(entity) {
+            return false;
+        }
+
+        let location = self.archetypes.spawn(entity);
+        self.entities.set_location(entity.id, location);
+
+        true
+    }
+
+    /// Includes components of type `T` in snapshots, stored under `name`. Loading a snapshot
+    /// needs the same names registered as saving it.
+    pub fn register_serializable<T: Component + Serialize + DeserializeOwned>(
+        &mut self,
+        name: &'static str,
+    ) {
+        info!("World::register_serializable() - {}", name);
+        self.snapshot_registry.register_component::<T>(name);
+    }
 
-    fn serialize() {}
+    /// Includes the bucket with this label, holding a `T`, in snapshots.
+    pub fn register_serializable_bucket<T: 'static + Send + Sync + Serialize + DeserializeOwned>(
+        &mut self,
+        label: &'static str,
+    ) {
+        info!("World::register_serializable_bucket() - {}", label);
+        self.snapshot_registry.register_bucket::<T>(label);
+    }
+
+    /// Encodes every living entity with its registered components, plus every registered
+    /// bucket. Components that were not registered are skipped with a warning.
+    pub fn serialize(&self, format: SnapshotFormat) -> Result<Vec<u8>, SnapshotError> {
+        let now = Instant::now();
+
+        let bytes = snapshot::encode(self, format)?;
+
+        debug!(
+            "World::serialize() - {} bytes in {} micros",
+            bytes.len(),
+            now.elapsed().as_micros().to_string()
+        );
+
+        Ok(bytes)
+    }
+
+    /// Restores a snapshot written by `serialize()` in either format. Entities keep their ids
+    /// and generations, so handles stored inside components stay valid.
+    ///
+    /// The world must not have living entities, and must have the snapshot's components and
+    /// buckets registered. Every component and bucket is decoded before anything is added, so
+    /// if loading fails the world is left as it was.
+    pub fn deserialize(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let now = Instant::now();
+
+        snapshot::decode(self, bytes)?;
+
+        debug!(
+            "World::deserialize() - {} entities in {} micros",
+            self.entities.living_entity_count,
+            now.elapsed().as_micros().to_string()
+        );
+
+        Ok(())
+    }
+
+    pub fn save_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<(), SnapshotError> {
+        let bytes = self.serialize(format)?;
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    pub fn load_snapshot(&mut self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let bytes = fs::read(path)?;
+        self.deserialize(&bytes)
+    }
+
+    fn spawn_random_terrain() {}
 }
 
 impl Default for World {
@@ -263,40 +1096,228 @@ impl Default for World {
     }
 }
 
-pub trait ComponentVec: Send + Sync {
-    fn as_any(&self) -> &(dyn std::any::Any + Send + Sync);
-    fn as_any_mut(&mut self) -> &mut (dyn std::any::Any + Send + Sync);
-    fn push_none(&mut self);
-    fn run_all(&mut self, storage: &RwLock<Storage>);
-    fn update_all(&mut self, storage: &mut RwLock<Storage>, input: &mut InputHandler, stats: &RwLock<Stats>);
-}
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pixpox_common::Camera;
+    use pixpox_utils::{InputHandler, SyntheticInput};
+    use winit::event::VirtualKeyCode;
+
+    use std::sync::{Arc, Mutex};
+
+    use super::World;
+    use crate::{
+        testing::{self, Name, NoPixelMap, Position, Velocity},
+        GlobalPixelMap, Stage, System,
+    };
 
-impl<T: 'static + Run + Update + Send + Sync> ComponentVec for Vec<Option<T>> {
-    fn as_any(&self) -> &(dyn std::any::Any + Send + Sync) {
-        self as &(dyn std::any::Any + Send + Sync)
+    // Counts the ticks that saw P pressed.
+    struct PressCounter {
+        presses: u32,
     }
 
-    fn as_any_mut(&mut self) -> &mut (dyn std::any::Any + Send + Sync) {
-        self as &mut (dyn std::any::Any + Send + Sync)
+    impl GlobalPixelMap for PressCounter {
+        fn render(&self, _pixels: &mut [u8]) {}
+
+        fn update(&mut self, input: &InputHandler) {
+            if input.winit.key_pressed(VirtualKeyCode::P) {
+                self.presses += 1;
+            }
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (1, 1)
+        }
+
+        fn get_camera(&self) -> Camera {
+            Camera::new(0, 0, 1, 1, 1, 1)
+        }
     }
 
-    fn push_none(&mut self) {
-        self.push(None)
+    fn presses(world: &World) -> u32 {
+        world
+            .storage
+            .read()
+            .unwrap()
+            .global_pixel_map::<PressCounter>()
+            .unwrap()
+            .presses
     }
 
-    fn run_all(&mut self, storage: &RwLock<Storage>) {
-        self.par_iter_mut().for_each(|component| {
-            if let Some(c) = component {
-                c.run(&storage.read().unwrap());
-            }
-        })
+    fn world() -> World {
+        let mut world = World::new();
+        world
+            .storage
+            .get_mut()
+            .unwrap()
+            .new_bucket::<PressCounter>("pixelmap", PressCounter { presses: 0 });
+        world.clock_mut().set_tick_rate(10.0);
+        world
     }
 
-    fn update_all(&mut self, storage: &mut RwLock<Storage>, input: &mut InputHandler, stats: &RwLock<Stats>) {
-        self.par_iter_mut().for_each(|component| {
-            if let Some(c) = component {
-                c.update(&storage, input, stats);
-            }
-        })
+    #[test]
+    fn press_is_kept_until_a_tick_runs() {
+        let mut world = world();
+        world
+            .input
+            .queue(&SyntheticInput::new().press_key(VirtualKeyCode::P));
+
+        assert_eq!(world.advance_by::<PressCounter>(Duration::from_millis(40)), 0);
+        assert_eq!(presses(&world), 0);
+
+        assert_eq!(world.advance_by::<PressCounter>(Duration::from_millis(60)), 1);
+        assert_eq!(presses(&world), 1);
+    }
+
+    #[test]
+    fn press_is_seen_by_one_tick_only() {
+        let mut world = world();
+        world
+            .input
+            .queue(&SyntheticInput::new().press_key(VirtualKeyCode::P));
+
+        assert_eq!(world.advance_by::<PressCounter>(Duration::from_millis(300)), 3);
+        assert_eq!(world.advance_by::<PressCounter>(Duration::from_millis(100)), 1);
+        assert_eq!(presses(&world), 1);
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut world = World::new();
+        let old = world.spawn_with((Position(1, 2),));
+
+        assert!(world.despawn(old));
+        assert!(!world.is_alive(old));
+        assert!(!world.despawn(old));
+
+        // The slot is reused, under a new generation
+        let new = world.spawn();
+        assert_eq!(new.id, old.id);
+        assert_ne!(new.generation, old.generation);
+
+        // The old handle does not reach the new entity
+        world.add_component_to_entity(old, Position(3, 4));
+        assert!(!world.has_component::<Position>(new));
+        assert!(world.get_component::<Position>(old).is_none());
+        assert!(world.get_component_mut::<Position>(old).is_none());
+        assert!(world.remove_component::<Position>(old).is_none());
+        assert!(!world.despawn(old));
+        assert!(world.is_alive(new));
+
+        world.add_component_to_entity(new, Position(5, 6));
+        assert!(world.get_component::<Position>(old).is_none());
+        assert_eq!(world.get_component::<Position>(new), Some(&Position(5, 6)));
+    }
+
+    #[test]
+    fn moving_between_archetypes_keeps_other_columns() {
+        let mut world = World::new();
+        let entities = world.spawn_batch((0..3).map(|i| (Position(i, i), Velocity(-i, -i))));
+
+        // Adding moves the first entity out, and the last one into its row
+        world.add_component_to_entity(entities[0], Name("first".to_string()));
+
+        assert_eq!(
+            world.get_component::<Position>(entities[0]),
+            Some(&Position(0, 0))
+        );
+        assert_eq!(
+            world.get_component::<Velocity>(entities[0]),
+            Some(&Velocity(0, 0))
+        );
+        assert_eq!(
+            world.get_component::<Name>(entities[0]),
+            Some(&Name("first".to_string()))
+        );
+
+        // Removing moves the second entity out
+        assert_eq!(
+            world.remove_component::<Position>(entities[1]),
+            Some(Position(1, 1))
+        );
+
+        assert!(!world.has_component::<Position>(entities[1]));
+        assert_eq!(
+            world.get_component::<Velocity>(entities[1]),
+            Some(&Velocity(-1, -1))
+        );
+
+        // The entity left behind, and the one moved out first, are untouched
+        for (entity, i) in [(entities[2], 2), (entities[0], 0)] {
+            assert_eq!(
+                world.get_component::<Position>(entity),
+                Some(&Position(i, i))
+            );
+            assert_eq!(
+                world.get_component::<Velocity>(entity),
+                Some(&Velocity(-i, -i))
+            );
+        }
+
+        // Removing the last component leaves an entity with none
+        assert_eq!(
+            world.remove_component::<Velocity>(entities[1]),
+            Some(Velocity(-1, -1))
+        );
+        assert!(world.is_alive(entities[1]));
+        assert!(!world.has_component::<Velocity>(entities[1]));
+        assert_eq!(
+            world.get_component::<Velocity>(entities[2]),
+            Some(&Velocity(-2, -2))
+        );
+    }
+
+    #[test]
+    fn startup_systems_run_once() {
+        let mut world = testing::world();
+        let runs = Arc::new(Mutex::new(Vec::new()));
+
+        for name in ["first", "second"] {
+            let runs = Arc::clone(&runs);
+            world.add_system(
+                System::new(name, move |ctx| runs.lock().unwrap().push(ctx.name()))
+                    .in_stage(Stage::Startup),
+            );
+        }
+        for _ in 0..3 {
+            world.run::<NoPixelMap>();
+        }
+        assert_eq!(*runs.lock().unwrap(), vec!["first", "second"]);
+
+        // One added later runs once on the next tick
+        let late_runs = Arc::clone(&runs);
+        world.add_system(
+            System::new("late", move |ctx| {
+                late_runs.lock().unwrap().push(ctx.name())
+            })
+            .in_stage(Stage::Startup),
+        );
+        for _ in 0..3 {
+            world.run::<NoPixelMap>();
+        }
+        assert_eq!(*runs.lock().unwrap(), vec!["first", "second", "late"]);
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut world = testing::world();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Added backwards, so the order cannot come from the order they were added in
+        for stage in Stage::ALL.into_iter().rev() {
+            let order = Arc::clone(&order);
+            world.add_system(
+                System::new(stage.name(), move |_| order.lock().unwrap().push(stage))
+                    .in_stage(stage),
+            );
+        }
+
+        world.run::<NoPixelMap>();
+        assert_eq!(*order.lock().unwrap(), Stage::ALL.to_vec());
+
+        order.lock().unwrap().clear();
+        world.run::<NoPixelMap>();
+        assert_eq!(*order.lock().unwrap(), Stage::ALL[1..].to_vec());
     }
 }
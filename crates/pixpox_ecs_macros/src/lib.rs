@@ -0,0 +1,227 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index, Lit, Meta, NestedMeta};
+
+/// Implements `pixpox_ecs::Bundle` for a struct whose fields are all components, so it can be
+/// spawned in one go with `World::spawn_with()` or `World::spawn_batch()`.
+///
+/// ### Example
+///
+/// ```ignore
+/// #[derive(Bundle)]
+/// struct Player {
+///     position: Position,
+///     velocity: Velocity,
+///     sprite: Sprite,
+/// }
+/// ```
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(name, "Bundle can only be derived for structs")
+                .to_compile_error()
+                .into()
+        },
+    };
+
+    let field_types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let field_access: Vec<_> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().expect("Named field without an ident");
+                quote! { #ident }
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|index| {
+                let index = Index::from(index);
+                quote! { #index }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::pixpox_ecs::Bundle for #name #ty_generics #where_clause {
+            fn type_ids() -> ::std::vec::Vec<::std::any::TypeId> {
+                ::std::vec![#(::std::any::TypeId::of::<#field_types>()),*]
+            }
+
+            fn register(&mut self, components: &mut ::pixpox_ecs::archetypes::Components) {
+                #(components.register_component(&mut self.#field_access);)*
+            }
+
+            unsafe fn push(self, archetype: &mut ::pixpox_ecs::archetypes::Archetype, change_tick: u32) {
+                #(unsafe { archetype.push_component(self.#field_access, change_tick) };)*
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+/// Implements `Label`, and no-op `Run` and `Update`, for a component.
+///
+/// The label is the type's name unless given with `#[component(label = "...")]`. A component
+/// that has its own `Run` or `Update` says so with `#[component(run)]` or `#[component(update)]`,
+/// and implements that trait by hand. The generated no-op hooks are never scheduled, so pure
+/// data costs nothing per tick.
+///
+/// ### Example
+///
+/// ```ignore
+/// #[derive(Component)]
+/// struct Velocity(f32, f32);
+///
+/// #[derive(Component)]
+/// #[component(label = "ConwayGrid", run, update)]
+/// struct ConwayGridComponent {
+///     inner: ConwayGrid,
+///     paused: bool,
+/// }
+/// ```
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_component(&input) {
+        Ok(expanded) => expanded.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_component(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut label = name.to_string();
+    let mut custom_run = false;
+    let mut custom_update = false;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("component"))
+    {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected #[component(label = \"...\", run, update)]",
+                ))
+            },
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("label") => {
+                    match &pair.lit {
+                        Lit::Str(value) => label = value.value(),
+                        lit => return Err(syn::Error::new_spanned(lit, "label must be a string")),
+                    }
+                },
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("run") => custom_run = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("update") => {
+                    custom_update = true
+                },
+                other => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "unknown component attribute, expected `label`, `run` or `update`",
+                    ))
+                },
+            }
+        }
+    }
+
+    let run = (!custom_run).then(|| {
+        quote! {
+            impl #impl_generics ::pixpox_ecs::Run for #name #ty_generics #where_clause {
+                const NOOP: bool = true;
+
+                fn run(
+                    _this: ::pixpox_ecs::Mut<Self>,
+                    _storage: &::pixpox_ecs::Storage,
+                    _commands: &::pixpox_ecs::Commands,
+                ) {
+                }
+            }
+        }
+    });
+
+    let update = (!custom_update).then(|| {
+        quote! {
+            impl #impl_generics ::pixpox_ecs::Update for #name #ty_generics #where_clause {
+                const NOOP: bool = true;
+
+                fn update(
+                    _this: ::pixpox_ecs::Mut<Self>,
+                    _storage: &::std::sync::RwLock<::pixpox_ecs::Storage>,
+                    _input: &::pixpox_ecs::InputHandler,
+                    _stats: &::std::sync::RwLock<::pixpox_ecs::Stats>,
+                    _commands: &::pixpox_ecs::Commands,
+                ) {
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::pixpox_ecs::Label for #name #ty_generics #where_clause {
+            fn label(&mut self) -> &'static str {
+                #label
+            }
+        }
+
+        #run
+        #update
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> syn::Result<String> {
+        let input: DeriveInput = syn::parse_str(source).expect("Could not parse test input");
+        expand_component(&input).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn label_defaults_to_the_type_name() {
+        let expanded = expand("struct Velocity(f32, f32);").unwrap();
+
+        assert!(expanded.contains("\"Velocity\""));
+        assert_eq!(expanded.matches("const NOOP : bool = true").count(), 2);
+    }
+
+    #[test]
+    fn label_run_and_update_are_read_from_the_attribute() {
+        let expanded = expand("#[component(label = \"Grid\", run)] struct ConwayGrid;").unwrap();
+        assert!(expanded.contains("\"Grid\""));
+        assert!(!expanded.contains("\"ConwayGrid\""));
+        assert!(!expanded.contains(":: pixpox_ecs :: Run for"));
+        assert!(expanded.contains(":: pixpox_ecs :: Update for"));
+
+        let expanded = expand("#[component(run, update)] struct ConwayGrid;").unwrap();
+        assert!(!expanded.contains("NOOP"));
+    }
+
+    #[test]
+    fn unknown_attributes_are_rejected() {
+        let err = expand("#[component(render)] struct Cell;").unwrap_err();
+        assert!(err.to_string().contains("unknown component attribute"));
+
+        let err = expand("#[component(label = 3)] struct Cell;").unwrap_err();
+        assert_eq!(err.to_string(), "label must be a string");
+    }
+}
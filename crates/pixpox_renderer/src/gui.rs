@@ -1,4 +1,4 @@
-use imgui::{ProgressBar, Ui};
+use imgui::{ProgressBar, TableColumnSetup, TableFlags, Ui};
 use pixpox_utils::Stats;
 
 use crate::{wgpu, Pixels, PixelsContext};
@@ -25,6 +25,56 @@ impl<'a> GuiChild<'a> {
     }
 }
 
+/// Draws the frame and tick rates and every `Stats` value, then a table of the frame time,
+/// the tick time and every timing sector with its last, min, median, avg, p95, p99 and max
+/// sample in milliseconds. Meant for a performance window.
+pub fn stats_table(ui: &Ui, stats: &Stats) {
+    ui.text(format!(
+        "fps: {:.1} (avg {:.1}, median {:.1})",
+        stats.get_fps(),
+        stats.get_average_fps(),
+        stats.get_median_fps()
+    ));
+    ui.text(format!(
+        "tps: {:.1} ({} ticks in {} frames)",
+        stats.get_tps(),
+        stats.total_ticks(),
+        stats.total_frames()
+    ));
+
+    for (label, value) in stats.values() {
+        ui.text(format!("{}: {}", label, value));
+    }
+
+    let columns = ["sector", "last ms", "min", "median", "avg", "p95", "p99", "max"]
+        .map(TableColumnSetup::new);
+    if let Some(_table) = ui.begin_table_header_with_flags(
+        "stats-sectors",
+        columns,
+        TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SIZING_FIXED_FIT,
+    ) {
+        let frames = [("frame", stats.frames()), ("tick", stats.ticks())];
+        for (label, sector) in frames.into_iter().chain(stats.sectors()) {
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(label);
+
+            for value in [
+                sector.last(),
+                sector.min(),
+                sector.median(),
+                sector.avg(),
+                sector.p95(),
+                sector.p99(),
+                sector.max(),
+            ] {
+                ui.table_next_column();
+                ui.text(format!("{:.3}", value * 1000.0));
+            }
+        }
+    }
+}
+
 /// Manages all state required for rendering Dear ImGui over `Pixels`.
 pub struct Gui<'a> {
     imgui: imgui::Context,
@@ -250,9 +250,13 @@ impl CellRealm {
         self.cells.iter().map(|cell| cell.get_color()).collect()
     }
 
-    // write a function that returns the amount of cells of each type
+    /// Number of cells of every type, including the types with none left.
     pub fn get_cell_count(&self) -> HashMap<Cell, usize> {
-        let mut cell_count: HashMap<Cell, usize> = HashMap::new();
+        let mut cell_count: HashMap<Cell, usize> =
+            [Cell::EMPTY, Cell::SAND, Cell::WATER, Cell::SOLID]
+                .into_iter()
+                .map(|cell| (cell, 0))
+                .collect();
         for cell in self.cells.iter() {
             let count = cell_count.entry(*cell).or_insert(0);
             *count += 1;
@@ -116,6 +116,11 @@ impl ConwayGrid {
         (x, y)
     }
 
+    // Number of cells that are alive
+    pub fn get_alive_count(&self) -> usize {
+        self.cells.iter().filter(|alive| **alive).count()
+    }
+
     pub fn clear_grid(&mut self) {
         self.cells = self.cells.iter().map(|_| false).collect();
     }
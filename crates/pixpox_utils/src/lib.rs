@@ -1,12 +1,24 @@
 pub mod stats;
 pub use stats::Stats;
 
+pub mod recorder;
+pub use recorder::Recorder;
+
+pub mod trace;
+pub use trace::Tracer;
+
 pub mod CA;
 pub use CA::conway;
 pub use CA::cell_realm;
 pub use CA::letters;
 use winit_input_helper::WinitInputHelper;
-use winit::event::{VirtualKeyCode, Event};
+use winit::{
+    event::{
+        DeviceId, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+        VirtualKeyCode, WindowEvent,
+    },
+    window::WindowId,
+};
 
 /// A macro similar to `vec![$elem; $size]` which returns a boxed array.
 ///
@@ -50,8 +62,13 @@ impl InputHandler {
         }
     }
 
+    /// Collects a window event into the input the next tick sees. Unlike a plain
+    /// `WinitInputHelper`, a new event loop iteration does not forget the presses and releases
+    /// collected so far, `clear_actions()` does, once a tick has seen them.
     pub fn update(&mut self, event: &Event<()>, mouse_pos: (isize, isize), prev_mouse_pos: (isize, isize)) {
-        self.winit.update(event);
+        if !matches!(event, Event::NewEvents(_)) {
+            self.winit.update(event);
+        }
         self.mouse = mouse_pos;
         self.mouse_prev = prev_mouse_pos;
     }
@@ -64,8 +81,110 @@ impl InputHandler {
     pub fn get_mouse_pos(&self) -> (isize, isize) {
         (self.mouse.0 / self.scale as isize, self.mouse.1 / self.scale as isize)
     }
+
+    /// Collects synthetic events into the input the next tick sees, e.g. to drive a headless
+    /// world. Keys and buttons held before stay held.
+    pub fn queue(&mut self, input: &SyntheticInput) {
+        // Never handed to winit itself, only to the input helper, which ignores these ids.
+        let window_id = unsafe { WindowId::dummy() };
+        let device_id = unsafe { DeviceId::dummy() };
+
+        for event in input.events.iter() {
+            let event = match *event {
+                SyntheticEvent::Key(key, state) => {
+                    #[allow(deprecated)]
+                    WindowEvent::KeyboardInput {
+                        device_id,
+                        input: KeyboardInput {
+                            scancode: 0,
+                            state,
+                            virtual_keycode: Some(key),
+                            modifiers: ModifiersState::empty(),
+                        },
+                        is_synthetic: true,
+                    }
+                },
+                SyntheticEvent::Mouse(button, state) => {
+                    #[allow(deprecated)]
+                    WindowEvent::MouseInput {
+                        device_id,
+                        state,
+                        button,
+                        modifiers: ModifiersState::empty(),
+                    }
+                },
+            };
+
+            let event: Event<()> = Event::WindowEvent { window_id, event };
+            self.winit.update(&event);
+        }
+
+        if let Some(mouse) = input.mouse {
+            self.mouse_prev = self.mouse;
+            self.mouse = mouse;
+        }
+    }
+
+    /// Forgets the presses and releases collected so far, keeping what is held. Called after
+    /// every tick, so a key press is seen by exactly one tick, however many ticks a frame runs,
+    /// and by the next tick to run if a frame runs none.
+    pub fn clear_actions(&mut self) {
+        self.winit.step_with_window_events(&[]);
+        self.mouse_prev = self.mouse;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SyntheticEvent {
+    Key(VirtualKeyCode, ElementState),
+    Mouse(MouseButton, ElementState),
+}
+
+/// Keyboard and mouse input for one tick of an `InputHandler`, without a window.
+///
+/// ```ignore
+/// let input = SyntheticInput::new()
+///     .press_key(VirtualKeyCode::P)
+///     .mouse_at((10, 20))
+///     .press_mouse(MouseButton::Left);
+///
+/// world.input.queue(&input);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticInput {
+    events: Vec<SyntheticEvent>,
+    mouse: Option<(isize, isize)>,
 }
 
-pub fn print_type_of<T>(_: &T) {
-    println!("{}", std::any::type_name::<T>())
-}
\ No newline at end of file
+impl SyntheticInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press_key(mut self, key: VirtualKeyCode) -> Self {
+        self.events.push(SyntheticEvent::Key(key, ElementState::Pressed));
+        self
+    }
+
+    pub fn release_key(mut self, key: VirtualKeyCode) -> Self {
+        self.events.push(SyntheticEvent::Key(key, ElementState::Released));
+        self
+    }
+
+    pub fn press_mouse(mut self, button: MouseButton) -> Self {
+        self.events.push(SyntheticEvent::Mouse(button, ElementState::Pressed));
+        self
+    }
+
+    pub fn release_mouse(mut self, button: MouseButton) -> Self {
+        self.events.push(SyntheticEvent::Mouse(button, ElementState::Released));
+        self
+    }
+
+    /// Moves the mouse to this pixel of the pixel map, the same coordinates `InputHandler::mouse`
+    /// holds. The previous position becomes `mouse_prev`.
+    pub fn mouse_at(mut self, mouse: (isize, isize)) -> Self {
+        self.mouse = Some(mouse);
+        self
+    }
+}
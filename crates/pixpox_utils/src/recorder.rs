@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde_json::json;
+
+/// # Recorder
+///
+/// Keeps a time series of every `Stats` value and sector while recording, one row per
+/// simulation tick, and writes them as CSV or JSON, e.g. to plot how a population grows over
+/// a run. Sector samples are in seconds, values are stored as they were set.
+///
+/// A `Stats` samples its recorder on every `Stats::new_tick()`. A row without a sample for a
+/// series, because the series first shows up part way through a recording or was not reported
+/// on that tick, is left empty in CSV and `null` in JSON.
+///
+/// ## Example
+///
+/// ```ignore
+/// world.stats.write().unwrap().recorder_mut().start();
+///
+/// runner.run(1000);
+///
+/// let mut stats = world.stats.write().unwrap();
+/// stats.recorder_mut().stop();
+/// stats.recorder().save("metrics.csv").expect("Could not save metrics");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    recording: bool,
+    ticks: Vec<u64>,                            // the tick each row was sampled at
+    series: BTreeMap<String, Vec<Option<f32>>>, // one entry per row
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new recording, dropping the rows of the previous one.
+    pub fn start(&mut self) {
+        self.clear();
+        self.recording = true;
+    }
+
+    /// Stops recording. The rows are kept until the next `start()` or `clear()`.
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+        self.series.clear();
+    }
+
+    /// Number of rows recorded.
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// The tick of every row.
+    pub fn ticks(&self) -> &[u64] {
+        &self.ticks
+    }
+
+    /// The series with this label, one entry per row.
+    pub fn get(&self, label: &str) -> Option<&[Option<f32>]> {
+        self.series.get(label).map(Vec::as_slice)
+    }
+
+    /// Every series, sorted by label.
+    pub fn series(&self) -> impl Iterator<Item = (&str, &[Option<f32>])> {
+        self.series
+            .iter()
+            .map(|(label, series)| (label.as_str(), series.as_slice()))
+    }
+
+    /// Adds a row for `tick`, if recording. Series without a sample in it get an empty entry.
+    pub fn sample<'a>(&mut self, tick: u64, samples: impl IntoIterator<Item = (&'a str, f32)>) {
+        if !self.recording {
+            return;
+        }
+
+        let row = self.ticks.len();
+        self.ticks.push(tick);
+
+        for (label, value) in samples {
+            let series = self
+                .series
+                .entry(label.to_owned())
+                .or_insert_with(|| vec![None; row]);
+
+            if series.len() == row {
+                series.push(Some(value));
+            }
+        }
+
+        for series in self.series.values_mut() {
+            series.resize(row + 1, None);
+        }
+    }
+
+    /// Writes a header of `tick` and every label, then one line per row.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "tick")?;
+        for label in self.series.keys() {
+            write!(writer, ",{}", csv_field(label))?;
+        }
+        writeln!(writer)?;
+
+        for (row, tick) in self.ticks.iter().enumerate() {
+            write!(writer, "{}", tick)?;
+            for series in self.series.values() {
+                match series[row] {
+                    Some(value) => write!(writer, ",{}", value)?,
+                    None => write!(writer, ",")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `{"ticks": [...], "series": {"label": [...], ...}}`.
+    pub fn write_json<W: Write>(&self, writer: W) -> io::Result<()> {
+        let recording = json!({
+            "ticks": self.ticks,
+            "series": self.series,
+        });
+
+        serde_json::to_writer(writer, &recording).map_err(io::Error::from)
+    }
+
+    /// Writes the recording to `path`, as JSON if it ends in `.json` and as CSV otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => self.write_json(&mut writer)?,
+            _ => self.write_csv(&mut writer)?,
+        }
+
+        writer.flush()
+    }
+}
+
+// Quotes a CSV field if it would otherwise be split or cut short.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recorder {
+        let mut recorder = Recorder::new();
+        recorder.start();
+
+        recorder.sample(1, [("sand", 3.0), ("water", 2.0)]);
+        recorder.sample(2, [("water", 1.0)]);
+        recorder.sample(3, [("sand, wet", 5.0)]);
+
+        recorder
+    }
+
+    #[test]
+    fn series_dropping_out_and_showing_up_are_padded() {
+        let recorder = recording();
+
+        assert_eq!(recorder.len(), 3);
+        assert_eq!(recorder.get("sand"), Some(&[Some(3.0), None, None][..]));
+        assert_eq!(
+            recorder.get("water"),
+            Some(&[Some(2.0), Some(1.0), None][..])
+        );
+        assert_eq!(
+            recorder.get("sand, wet"),
+            Some(&[None, None, Some(5.0)][..])
+        );
+    }
+
+    #[test]
+    fn writes_gaps_as_empty_csv_fields() {
+        let mut csv = Vec::new();
+        recording().write_csv(&mut csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "tick,sand,\"sand, wet\",water\n1,3,,2\n2,,,1\n3,,5,\n"
+        );
+    }
+
+    #[test]
+    fn writes_gaps_as_json_null() {
+        let mut json = Vec::new();
+        recording().write_json(&mut json).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["ticks"], serde_json::json!([1, 2, 3]));
+        assert_eq!(json["series"]["sand"], serde_json::json!([3.0, null, null]));
+    }
+
+    #[test]
+    fn does_not_sample_unless_recording() {
+        let mut recorder = recording();
+        recorder.stop();
+        recorder.sample(4, [("sand", 1.0)]);
+
+        assert_eq!(recorder.len(), 3);
+    }
+}
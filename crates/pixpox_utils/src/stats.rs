@@ -1,68 +1,467 @@
 use std::{
-    collections::HashMap,
-    fmt::format,
+    collections::{BTreeMap, VecDeque},
     time::{self, Instant},
 };
 
+use crate::recorder::Recorder;
+
+/// Number of samples a sector keeps, i.e. 10 seconds at 60 ticks per second.
+pub const SECTOR_WINDOW: usize = 600;
+
+/// A timing sector: the most recent `SECTOR_WINDOW` samples, in seconds, of something that is
+/// measured every tick, such as a component type's `run()`. Older samples are dropped, so the
+/// statistics below always cover the last few seconds rather than the whole run.
+#[derive(Debug, Clone)]
+pub struct Sector {
+    samples: VecDeque<f32>,
+}
+
+impl Sector {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(SECTOR_WINDOW),
+        }
+    }
+
+    fn push(&mut self, value: f32) {
+        if self.samples.len() == SECTOR_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /// Number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The most recent sample.
+    pub fn last(&self) -> f32 {
+        self.samples.back().copied().unwrap_or(0.0)
+    }
+
+    pub fn min(&self) -> f32 {
+        self.samples.iter().copied().reduce(f32::min).unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f32 {
+        self.samples.iter().copied().reduce(f32::max).unwrap_or(0.0)
+    }
+
+    pub fn sum(&self) -> f32 {
+        self.samples.iter().sum()
+    }
+
+    pub fn avg(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        self.sum() / self.samples.len() as f32
+    }
+
+    /// The smallest sample that `percentile` percent of the window are less than or equal to.
+    pub fn percentile(&self, percentile: f32) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+        sorted[rank.saturating_sub(1)]
+    }
+
+    /// The middle sample, or the mean of the two middle samples if there is an even number.
+    // `usize::is_multiple_of()` is newer than the Rust versions we support
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    pub fn median(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let middle = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        }
+    }
+
+    pub fn p95(&self) -> f32 {
+        self.percentile(95.0)
+    }
+
+    pub fn p99(&self) -> f32 {
+        self.percentile(99.0)
+    }
+}
+
+// Turns a time per frame into a rate, without dividing by zero.
+fn per_second(seconds: f32) -> f32 {
+    if seconds > 0.0 {
+        1.0 / seconds
+    } else {
+        0.0
+    }
+}
+
+/// Frame and tick timings over the last `SECTOR_WINDOW` frames and ticks, plus any number of
+/// named sectors and values.
+///
+/// Rendered frames and simulation ticks are tracked separately, since the fixed timestep runs
+/// zero or more ticks per frame: `new_frame()` is called once per frame and `new_tick()` once
+/// per tick. Everything only keeps a recent window; use `recorder_mut()` to keep the whole
+/// history of a run.
 pub struct Stats {
-    last_update: time::Instant,
-    fps: f32,
-    acc: Vec<f32>,
-    sectors: HashMap<String, f32>,
+    last_frame: Option<time::Instant>,
+    frames: Sector,          // seconds between consecutive frames
+    ticks: Sector,           // seconds each tick took to run
+    ticks_per_frame: Sector, // ticks run between consecutive frames
+    ticks_this_frame: u32,
+    total_frames: u64,
+    total_ticks: u64,
+    sectors: BTreeMap<String, (Sector, u64)>, // with `total_ticks` when it was last pushed to
+    values: BTreeMap<String, (f32, u64)>,     // with `total_ticks` when it was set
+    recorder: Recorder,
 }
 
 impl Stats {
     pub fn new() -> Self {
         Self {
-            last_update: Instant::now(),
-            fps: 0.0,
-            acc: Vec::with_capacity(100000),
-            sectors: HashMap::new(),
+            last_frame: None,
+            frames: Sector::new(),
+            ticks: Sector::new(),
+            ticks_per_frame: Sector::new(),
+            ticks_this_frame: 0,
+            total_frames: 0,
+            total_ticks: 0,
+            sectors: BTreeMap::new(),
+            values: BTreeMap::new(),
+            recorder: Recorder::new(),
+        }
+    }
+
+    /// Marks the start of a rendered frame. The first call only starts the clock, so the time
+    /// spent starting up does not count as a frame.
+    pub fn new_frame(&mut self) {
+        let now = Instant::now();
+
+        if let Some(last_frame) = self.last_frame {
+            self.frames.push((now - last_frame).as_secs_f32());
+            self.ticks_per_frame.push(self.ticks_this_frame as f32);
+            self.total_frames += 1;
+        }
+
+        self.ticks_this_frame = 0;
+        self.last_frame = Some(now);
+    }
+
+    /// Records a simulation tick that took `duration` seconds to run, and samples the tick
+    /// time, every value set during the tick and the sample every sector got during the tick
+    /// into the recorder, if it is recording. Call it once everything else about the tick has
+    /// been recorded.
+    ///
+    /// A value or sector that was not updated during the tick keeps showing its latest state,
+    /// but is not recorded for the tick, so a count or timing that is no longer reported does
+    /// not live on in the recording.
+    pub fn new_tick(&mut self, duration: f32) {
+        let tick = self.total_ticks;
+
+        self.ticks.push(duration);
+        self.ticks_this_frame += 1;
+        self.total_ticks += 1;
+
+        if self.recorder.is_recording() {
+            let values = self
+                .values
+                .iter()
+                .filter(|(_, (_, set_at))| *set_at == tick)
+                .map(|(label, (value, _))| (label.as_str(), *value));
+            let sectors = self
+                .sectors
+                .iter()
+                .filter(|(_, (_, pushed_at))| *pushed_at == tick)
+                .map(|(label, (sector, _))| (label.as_str(), sector.last()));
+
+            self.recorder.sample(
+                self.total_ticks,
+                [("tick time", duration)]
+                    .into_iter()
+                    .chain(values)
+                    .chain(sectors),
+            );
         }
     }
 
-    pub fn new_tick(&mut self) {
-        let curr_diff = Instant::now() - self.last_update;
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
 
-        self.fps = 1.0 / curr_diff.as_secs_f32();
-        self.acc.push(self.fps);
-        self.last_update = Instant::now();
+    pub fn recorder_mut(&mut self) -> &mut Recorder {
+        &mut self.recorder
     }
 
+    /// Seconds between consecutive frames, over the window.
+    pub fn frames(&self) -> &Sector {
+        &self.frames
+    }
+
+    /// Seconds each simulation tick took to run, over the window.
+    pub fn ticks(&self) -> &Sector {
+        &self.ticks
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    pub fn total_ticks(&self) -> u64 {
+        self.total_ticks
+    }
+
+    /// Frames per second, going by the last frame only.
     pub fn get_fps(&self) -> f32 {
-        self.fps
+        per_second(self.frames.last())
     }
 
     pub fn get_fps_as_string(&self) -> String {
-        self.fps.to_string()
+        self.get_fps().to_string()
     }
 
+    /// Frames per second over the window.
     pub fn get_average_fps(&self) -> f32 {
-        self.acc.iter().sum::<f32>() as f32 / self.acc.len() as f32
+        per_second(self.frames.avg())
     }
 
+    /// Frames per second at the median frame time over the window, which, unlike the average,
+    /// a few long frames do not drag down.
+    pub fn get_median_fps(&self) -> f32 {
+        per_second(self.frames.median())
+    }
+
+    #[deprecated(note = "was never implemented and always returned 0, use `get_median_fps()`")]
     pub fn get_mean_fps(&self) -> f32 {
-        // self.acc.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        // self.acc[self.acc.len() / 2]
-        0.0
+        self.get_median_fps()
     }
 
-    pub fn update_sector(&mut self, label: String, value: f32) {
-        self.sectors.insert(label, value);
+    /// Simulation ticks per second over the window of frames, i.e. how fast the simulation
+    /// actually runs next to the frame rate.
+    pub fn get_tps(&self) -> f32 {
+        let seconds = self.frames.sum();
+        if seconds > 0.0 {
+            self.ticks_per_frame.sum() / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Adds a timing sample, in seconds, to the sector with this label. The label is only
+    /// copied the first time it is used.
+    pub fn update_sector(&mut self, label: impl AsRef<str> + Into<String>, value: f32) {
+        let tick = self.total_ticks;
+
+        match self.sectors.get_mut(label.as_ref()) {
+            Some((sector, pushed_at)) => {
+                sector.push(value);
+                *pushed_at = tick;
+            },
+            None => {
+                let mut sector = Sector::new();
+                sector.push(value);
+                self.sectors.insert(label.into(), (sector, tick));
+            },
+        }
+    }
+
+    pub fn sector(&self, label: &str) -> Option<&Sector> {
+        self.sectors.get(label).map(|(sector, _)| sector)
+    }
+
+    /// Every sector, sorted by label.
+    pub fn sectors(&self) -> impl Iterator<Item = (&str, &Sector)> {
+        self.sectors
+            .iter()
+            .map(|(label, (sector, _))| (label.as_str(), sector))
+    }
+
+    /// Sets a value that only its latest state matters for, such as a count. Set it on every
+    /// tick it should be recorded for, including when it drops to zero.
+    pub fn update_value(&mut self, label: impl AsRef<str> + Into<String>, value: f32) {
+        let entry = (value, self.total_ticks);
+
+        match self.values.get_mut(label.as_ref()) {
+            Some(value) => *value = entry,
+            None => {
+                self.values.insert(label.into(), entry);
+            },
+        }
+    }
+
+    pub fn value(&self, label: &str) -> Option<f32> {
+        self.values.get(label).map(|(value, _)| *value)
+    }
+
+    /// Every value, sorted by label.
+    pub fn values(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.values
+            .iter()
+            .map(|(label, (value, _))| (label.as_str(), *value))
     }
 
     pub fn get_formatted_stats(&self) -> Vec<String> {
         let mut ret = Vec::new();
 
-        ret.push("fps: ".to_owned() + &self.get_fps().to_string());
-        ret.push("avg fps: ".to_owned() + &self.get_average_fps().to_string());
-        ret.push("mean fps: ".to_owned() + &self.get_mean_fps().to_string());
+        ret.push(format!(
+            "fps: {:.1} (avg {:.1}, median {:.1}), tps: {:.1}",
+            self.get_fps(),
+            self.get_average_fps(),
+            self.get_median_fps(),
+            self.get_tps()
+        ));
+
+        for (k, sector) in [("frame", &self.frames), ("tick", &self.ticks)] {
+            ret.push(format!(
+                "{} time: {:.3} ms (min {:.3}, median {:.3}, p95 {:.3}, p99 {:.3}, max {:.3})",
+                k,
+                sector.last() * 1000.0,
+                sector.min() * 1000.0,
+                sector.median() * 1000.0,
+                sector.p95() * 1000.0,
+                sector.p99() * 1000.0,
+                sector.max() * 1000.0
+            ));
+        }
+
+        for (k, v) in self.values() {
+            ret.push(format!("{}: {}", k, v));
+        }
 
-        for (k, v) in self.sectors.iter() {
-            let s = format!("{}: {}", k, v);
-            ret.push(s);
+        for (k, sector) in self.sectors() {
+            ret.push(format!(
+                "{}: {:.3} ms (min {:.3}, avg {:.3}, max {:.3}, p99 {:.3})",
+                k,
+                sector.last() * 1000.0,
+                sector.min() * 1000.0,
+                sector.avg() * 1000.0,
+                sector.max() * 1000.0,
+                sector.p99() * 1000.0
+            ));
         }
 
         ret
     }
 }
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sector(samples: impl IntoIterator<Item = f32>) -> Sector {
+        let mut sector = Sector::new();
+        for sample in samples {
+            sector.push(sample);
+        }
+        sector
+    }
+
+    #[test]
+    fn median_of_odd_and_even_windows() {
+        assert_eq!(sector([3.0, 1.0, 2.0]).median(), 2.0);
+        assert_eq!(sector([4.0, 1.0, 3.0, 2.0]).median(), 2.5);
+        assert_eq!(sector([7.0]).median(), 7.0);
+    }
+
+    #[test]
+    fn percentile_ranks() {
+        let hundred = sector((1..=100).rev().map(|i| i as f32));
+        assert_eq!(hundred.p95(), 95.0);
+        assert_eq!(hundred.p99(), 99.0);
+        assert_eq!(hundred.percentile(100.0), 100.0);
+        assert_eq!(hundred.percentile(0.0), 1.0);
+        assert_eq!(hundred.percentile(150.0), 100.0);
+
+        // With fewer samples than percent steps, the rank rounds up
+        let ten = sector((1..=10).map(|i| i as f32));
+        assert_eq!(ten.p95(), 10.0);
+        assert_eq!(ten.p99(), 10.0);
+        assert_eq!(ten.percentile(90.0), 9.0);
+        assert_eq!(ten.percentile(91.0), 10.0);
+        assert_eq!(ten.percentile(10.0), 1.0);
+    }
+
+    #[test]
+    fn empty_window() {
+        let empty = Sector::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.median(), 0.0);
+        assert_eq!(empty.p95(), 0.0);
+        assert_eq!(empty.p99(), 0.0);
+        assert_eq!(empty.avg(), 0.0);
+        assert_eq!(empty.last(), 0.0);
+    }
+
+    #[test]
+    fn oldest_samples_are_evicted() {
+        let sector = sector((0..SECTOR_WINDOW + 10).map(|i| i as f32));
+
+        assert_eq!(sector.len(), SECTOR_WINDOW);
+        assert_eq!(sector.min(), 10.0);
+        assert_eq!(sector.last(), (SECTOR_WINDOW + 9) as f32);
+        assert_eq!(sector.median(), (SECTOR_WINDOW as f32 + 19.0) / 2.0);
+    }
+
+    #[test]
+    fn values_and_sectors_not_set_during_a_tick_are_not_recorded() {
+        let mut stats = Stats::new();
+        stats.recorder_mut().start();
+
+        stats.update_value("sand".to_string(), 3.0);
+        stats.update_value("water".to_string(), 2.0);
+        stats.update_sector("run(): Sand", 0.5);
+        stats.new_tick(0.001);
+
+        // the sand is gone and no longer reported
+        stats.update_value("water".to_string(), 1.0);
+        stats.new_tick(0.001);
+
+        // reported again, as an explicit zero
+        stats.update_value("sand".to_string(), 0.0);
+        stats.update_sector("run(): Sand", 0.25);
+        stats.new_tick(0.001);
+
+        let recorder = stats.recorder();
+        assert_eq!(recorder.ticks(), &[1, 2, 3]);
+        assert_eq!(
+            recorder.get("sand"),
+            Some(&[Some(3.0), None, Some(0.0)][..])
+        );
+        assert_eq!(
+            recorder.get("water"),
+            Some(&[Some(2.0), Some(1.0), None][..])
+        );
+
+        assert_eq!(
+            recorder.get("run(): Sand"),
+            Some(&[Some(0.5), None, Some(0.25)][..])
+        );
+
+        // the latest state is still shown
+        assert_eq!(stats.value("water"), Some(1.0));
+        assert_eq!(stats.sector("run(): Sand").map(Sector::len), Some(2));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // Small, stable ids for the trace viewer, as `std::thread::ThreadId` has no stable integer.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+struct TraceEvent {
+    category: &'static str,
+    name: Cow<'static, str>,
+    start: Duration, // since the capture started
+    duration: Duration,
+    thread: u64,
+}
+
+struct Capture {
+    started: Instant,
+    events: Vec<TraceEvent>,
+    threads: BTreeMap<u64, String>,
+}
+
+/// # Tracer
+///
+/// Records how long the phases of a frame take while a capture is running, and writes them as
+/// a Chrome `trace_event` JSON file, which opens in Perfetto (ui.perfetto.dev) or
+/// `chrome://tracing`. Phases are timed with `span()`, which costs next to nothing while no
+/// capture is running, and may be recorded from any thread.
+///
+/// ## Example
+///
+/// ```ignore
+/// world.tracer.start();
+///
+/// {
+///     let _span = world.tracer.span("app", "ImGui render");
+///     gui.render(...);
+/// }
+///
+/// world.tracer.stop();
+/// world.tracer.save("trace.json").expect("Could not save trace");
+/// ```
+pub struct Tracer {
+    recording: AtomicBool,
+    capture: Mutex<Capture>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self {
+            recording: AtomicBool::new(false),
+            capture: Mutex::new(Capture {
+                started: Instant::now(),
+                events: Vec::new(),
+                threads: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Starts a new capture, dropping the events of the previous one.
+    pub fn start(&self) {
+        let mut capture = self.capture.lock().expect("Could not lock trace");
+        capture.started = Instant::now();
+        capture.events.clear();
+        capture.threads.clear();
+
+        self.recording.store(true, Ordering::Release);
+    }
+
+    /// Stops the capture. Its events are kept until the next `start()`.
+    pub fn stop(&self) {
+        self.recording.store(false, Ordering::Release);
+    }
+
+    /// Starts a capture if none is running and stops it otherwise. Returns whether one is
+    /// running now.
+    pub fn toggle(&self) -> bool {
+        if self.is_recording() {
+            self.stop();
+        } else {
+            self.start();
+        }
+
+        self.is_recording()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Acquire)
+    }
+
+    /// Time since the current, or last, capture started.
+    pub fn elapsed(&self) -> Duration {
+        self.capture
+            .lock()
+            .expect("Could not lock trace")
+            .started
+            .elapsed()
+    }
+
+    /// Number of events in the current, or last, capture.
+    pub fn len(&self) -> usize {
+        self.capture
+            .lock()
+            .expect("Could not lock trace")
+            .events
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Times a phase from now until the returned span is dropped. The category groups phases
+    /// in the trace viewer, e.g. `"stage"` or `"component"`.
+    pub fn span(&self, category: &'static str, name: impl Into<Cow<'static, str>>) -> Span<'_> {
+        Span {
+            tracer: self,
+            category,
+            name: name.into(),
+            start: self.is_recording().then(Instant::now),
+        }
+    }
+
+    fn record(&self, category: &'static str, name: Cow<'static, str>, start: Instant) {
+        let duration = start.elapsed();
+        let thread = THREAD_ID.with(|id| *id);
+
+        let mut capture = self.capture.lock().expect("Could not lock trace");
+
+        // A capture that was stopped, or restarted, while the span ran does not get it.
+        if !self.is_recording() || start < capture.started {
+            return;
+        }
+
+        capture.threads.entry(thread).or_insert_with(|| {
+            thread::current()
+                .name()
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("thread {}", thread))
+        });
+
+        let start = start - capture.started;
+        capture.events.push(TraceEvent {
+            category,
+            name,
+            start,
+            duration,
+            thread,
+        });
+    }
+
+    /// Writes the current, or last, capture as Chrome `trace_event` JSON.
+    pub fn write_json<W: Write>(&self, writer: W) -> io::Result<()> {
+        let capture = self.capture.lock().expect("Could not lock trace");
+
+        let mut events: Vec<Value> =
+            Vec::with_capacity(capture.events.len() + capture.threads.len() + 1);
+
+        events.push(json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": 1,
+            "args": { "name": "pixpox" },
+        }));
+
+        for (thread, name) in capture.threads.iter() {
+            events.push(json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": 1,
+                "tid": thread,
+                "args": { "name": name },
+            }));
+        }
+
+        // Complete events, with timestamps and durations in microseconds.
+        for event in capture.events.iter() {
+            events.push(json!({
+                "name": event.name,
+                "cat": event.category,
+                "ph": "X",
+                "ts": event.start.as_secs_f64() * 1_000_000.0,
+                "dur": event.duration.as_secs_f64() * 1_000_000.0,
+                "pid": 1,
+                "tid": event.thread,
+            }));
+        }
+
+        let trace = json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+        });
+
+        serde_json::to_writer(writer, &trace).map_err(io::Error::from)
+    }
+
+    /// Writes the current, or last, capture to a JSON file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_json(&mut writer)?;
+        writer.flush()
+    }
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A phase being timed by `Tracer::span()`. It is recorded when dropped, if a capture was
+/// running for all of it.
+#[must_use = "a span is recorded when it is dropped, so it times nothing if dropped right away"]
+pub struct Span<'a> {
+    tracer: &'a Tracer,
+    category: &'static str,
+    name: Cow<'static, str>,
+    start: Option<Instant>,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let name = std::mem::take(&mut self.name);
+            self.tracer.record(self.category, name, start);
+        }
+    }
+}
@@ -4,7 +4,7 @@ use log::{debug, error};
 use pixpox_app::App;
 use pixpox_ecs::{
     entity::{self, Entity},
-    InputHandler, Label, Run, Storage, Texture, Update, World,
+    Commands, Component, Mut, InputHandler, Run, Storage, Texture, Update, World,
 };
 use pixpox_utils::{conway::ConwayGrid, Stats};
 use winit::{
@@ -15,10 +15,12 @@ use winit_input_helper::WinitInputHelper;
 
 use pixpox_renderer::global_pixel_map::GlobalPixelMap;
 
-#[derive(Clone)]
+#[derive(Clone, Component)]
+#[component(label = "ConwayGrid", run, update)]
 pub struct ConwayGridComponent {
     inner: ConwayGrid,
     paused: bool,
+    redraw: bool, // the grid changed since it was last drawn
 }
 
 impl ConwayGridComponent {
@@ -26,50 +28,62 @@ impl ConwayGridComponent {
         Self {
             inner: ConwayGrid::new(height, width, gen_chance),
             paused: true,
+            redraw: true,
         }
     }
 }
 
 impl Run for ConwayGridComponent {
-    fn run(&mut self, _storage: &pixpox_ecs::Storage) {
-        if self.paused {
+    fn run(mut this: Mut<Self>, _storage: &pixpox_ecs::Storage, _commands: &Commands) {
+        if this.paused {
             return;
         }
 
-        self.inner.next_state();
+        this.inner.next_state();
+        this.redraw = true;
     }
 }
 
 impl Update for ConwayGridComponent {
-    fn update(&mut self, storage: &RwLock<pixpox_ecs::Storage>, input: &InputHandler, stats: &RwLock<Stats>) {
-        let mut storage = storage.write().unwrap();
+    fn update(
+        mut this: Mut<Self>,
+        storage: &RwLock<pixpox_ecs::Storage>,
+        input: &InputHandler,
+        stats: &RwLock<Stats>,
+        _commands: &Commands,
+    ) {
+        let storage = storage.read().unwrap();
 
         if input.winit.key_pressed(VirtualKeyCode::P) {
             log::info!("Toggled world");
-            self.paused = !self.paused;
+            this.paused = !this.paused;
         }
 
         if input.winit.key_pressed(VirtualKeyCode::C) {
             log::info!("Clear grid");
-            self.inner.clear_grid();
+            this.inner.clear_grid();
+            this.redraw = true;
         }
 
         if input.winit.mouse_held(0) {
             log::info!("mouse pos: [{}, {}]", input.mouse.0, input.mouse.1);
-            self.inner.set_line(input.mouse, input.mouse_prev, true);
+            this.inner.set_line(input.mouse, input.mouse_prev, true);
+            this.redraw = true;
         }
 
-        // Fetch PixelMap
-        let pixelmap = storage
-            .query_storage_mut::<GlobalPixelMap>("pixelmap")
-            .expect("Could not query Pixel Map");
+        // Only draw when the grid changed, so a paused grid does not get rendered every frame
+        if this.redraw {
+            let mut pixelmap = storage
+                .write_storage::<GlobalPixelMap>("pixelmap")
+                .expect("Could not query Pixel Map");
 
-        pixelmap.draw_flat_vec(&mut self.inner.get_color_vec());
-    }
-}
+            pixelmap.draw_flat_vec(&mut this.inner.get_color_vec());
+            this.redraw = false;
+        }
 
-impl Label for ConwayGridComponent {
-    fn label(&mut self) -> &'static str {
-        "ConwayGrid"
+        stats
+            .write()
+            .expect("Could not write lock stats")
+            .update_value("alive cells".to_string(), this.inner.get_alive_count() as f32);
     }
 }
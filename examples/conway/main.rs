@@ -23,7 +23,7 @@ use pixpox_app::Config;
 use pixpox_ecs::entity::Entity;
 use pixpox_ecs::{world, Texture};
 use pixpox_ecs::{Run, Update};
-use pixpox_renderer::gui::{GuiChild, GuiParent};
+use pixpox_renderer::gui::{stats_table, GuiChild, GuiParent};
 use pixpox_utils::{conway::ConwayGrid, Stats};
 use rand::Rng;
 use winit::dpi::{LogicalPosition, Position};
@@ -77,12 +77,10 @@ async fn run() {
         // ui.show_metrics_window(state);
         ui.window("Conway Performance (World)")
             .position([60.0, 60.0], imgui::Condition::Once)
-            .size([200.0, 200.0], imgui::Condition::FirstUseEver)
+            .size([400.0, 300.0], imgui::Condition::FirstUseEver)
             .collapsible(true)
             .build(|| {
-                for s in stats.get_formatted_stats().iter() {
-                    ui.text(s);
-                }
+                stats_table(ui, stats);
             });
     };
 
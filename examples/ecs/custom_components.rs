@@ -5,7 +5,7 @@ use log::{debug, error};
 use pixpox_app::App;
 use pixpox_ecs::{
     entity::{self, Entity},
-    Label, Run, Storage, Texture, Update, World, InputHandler,
+    Commands, Component, Mut, Run, Storage, Texture, Update, World, InputHandler,
 };
 use pixpox_utils::{conway::ConwayGrid, Stats};
 use winit::dpi::{LogicalPosition, Position};
@@ -14,10 +14,10 @@ use winit_input_helper::WinitInputHelper;
 use crate::GlobalPixelMap;
 
 // Cell
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Component)]
+#[component(run, update)]
 pub struct Cell {
     entity_id: usize,
-    label: &'static str,
 
     pos: (isize, isize),
     state: bool,
@@ -27,7 +27,8 @@ pub struct Cell {
 }
 
 impl Cell {
-    pub fn new(entity_id: usize, pos: (isize, isize), state: bool) -> Self {
+    /// Creates a cell not yet tied to an entity, see `set_entity()`.
+    pub fn new(pos: (isize, isize), state: bool) -> Self {
         let color = if state {
             [255, 0, 0, 255]
         } else {
@@ -35,8 +36,7 @@ impl Cell {
         };
 
         Self {
-            entity_id,
-            label: "Cell",
+            entity_id: 0,
             pos,
             state,
             heat: 0,
@@ -44,66 +44,78 @@ impl Cell {
             change: false
         }
     }
-}
 
-impl Label for Cell {
-    fn label(&mut self) -> &'static str {
-        return self.label;
+    pub fn set_entity(&mut self, entity: Entity) {
+        self.entity_id = entity.id;
     }
 }
 
 impl Run for Cell {
-    fn run(&mut self, storage: &Storage) {
+    fn run(mut this: Mut<Self>, storage: &Storage, _commands: &Commands) {
         let optim_grid = storage
-            .query_storage::<ConwayGrid>("optim_grid")
-            .expect("Could not query optim_grid");
+            .resource::<ConwayGrid>()
+            .expect("Could not query ConwayGrid");
 
-        let neibs = optim_grid.count_neibs(self.pos);
+        let neibs = optim_grid.count_neibs(this.pos);
         // error!("neibs: {}", neibs);
 
-        if self.state {
-            self.state = neibs == 2 || neibs == 3;
+        let state = if this.state {
+            neibs == 2 || neibs == 3
         } else {
-            self.state = neibs == 3;
-        }
+            neibs == 3
+        };
 
-        self.heat = if self.state == true {
+        let heat = if state == true {
             255
-        } else if self.heat > 0 {
-            self.heat - 1
+        } else if this.heat > 0 {
+            this.heat - 1
         } else {
             0
         };
 
         // Update cell color
-        let old_color = self.color;
-        self.color = if self.state == true {
+        let color = if state == true {
             [255, 0, 0, 255]
         } else {
-            [self.heat, 0, 0, 50]
+            [heat, 0, 0, 50]
         };
 
-        self.change = old_color != self.color;
+        // The color follows the state and heat, so only write (and mark the cell changed) when
+        // it changes, or to clear the change from last tick.
+        let change = color != this.color;
+        if change || this.change {
+            this.state = state;
+            this.heat = heat;
+            this.color = color;
+            this.change = change;
+        }
     }
 }
 
 impl Update for Cell {
-    fn update(&mut self, rw_storage: &RwLock<Storage>, input: &InputHandler, stats: &RwLock<Stats>) {
-        if self.change {
-            let mut storage = rw_storage.write().unwrap();
+    fn update(
+        this: Mut<Self>,
+        rw_storage: &RwLock<Storage>,
+        input: &InputHandler,
+        stats: &RwLock<Stats>,
+        _commands: &Commands,
+    ) {
+        if this.change {
+            let storage = rw_storage.read().unwrap();
 
             // Fetch & Update cell in grid
-            let grid = storage
-                .query_storage_mut::<ConwayGrid>("optim_grid")
-                .expect("Could not get optim_grid");
+            let mut grid = storage
+                .write_resource::<ConwayGrid>()
+                .expect("Could not get ConwayGrid");
 
-            grid.set_cell(self.pos, self.state);
+            grid.set_cell(this.pos, this.state);
+            drop(grid);
 
-            let pixelmap = storage
-                .query_storage_mut::<GlobalPixelMap>("pixelmap")
+            let mut pixelmap = storage
+                .write_storage::<GlobalPixelMap>("pixelmap")
                 .expect("Could not query Pixel Map");
 
-            pixelmap.draw_pos((self.pos.0, self.pos.1), self.color);
+            pixelmap.draw_pos((this.pos.0, this.pos.1), this.color);
         }
     }
 }
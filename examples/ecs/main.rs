@@ -22,7 +22,7 @@ use pixpox_app::Config;
 use pixpox_ecs::entity::Entity;
 use pixpox_ecs::{Run, InputHandler};
 use pixpox_ecs::{world, Texture};
-use pixpox_renderer::gui::{GuiChild, GuiParent};
+use pixpox_renderer::gui::{stats_table, GuiChild, GuiParent};
 use pixpox_common::Camera;
 use pixpox_utils::{Stats, conway::ConwayGrid, CA::letters};
 use rand::Rng;
@@ -69,23 +69,29 @@ async fn run() {
     let mut optim_grid: ConwayGrid = ConwayGrid::new(cfg.window_width, cfg.window_height, 0.10);
 
     // Initialise world; fill global data structures
+    let mut cells = Vec::with_capacity((cfg.window_height * cfg.window_width) as usize);
     for y in 0..cfg.window_height {
         for x in 0..cfg.window_width {
-            let entity = app.world.spawn();
-
             let pos = (x as isize, y as isize);
             let alive = rng.gen_bool(0.10);
 
-            let cell_component = Cell::new(entity.id, pos, alive);
-
-            app.world.add_component_to_entity(entity, cell_component);
+            cells.push((Cell::new(pos, alive),));
 
             optim_grid.set_cell(pos, alive);
-
-            entities_count += 1;
         }
     }
 
+    // Let every cell know which entity it belongs to
+    let entities = app.world.spawn_batch(cells);
+    for entity in entities.iter() {
+        app.world
+            .get_component_mut::<Cell>(*entity)
+            .expect("Could not get Cell")
+            .set_entity(*entity);
+    }
+
+    entities_count += entities.len();
+
     // Define UI Callbacks and States
     let mut show_metrics_state = &mut false;
     let mut show_metrics_closure = |ui: &mut Ui, state: &mut bool, stats: &Stats| {
@@ -97,9 +103,7 @@ async fn run() {
             .build(|| {
                 ui.text("entities: ".to_owned() + &entities_count.to_string());
 
-                for s in stats.get_formatted_stats().iter() {
-                    ui.text(s);
-                };
+                stats_table(ui, stats);
             });
     };
 
@@ -130,7 +134,7 @@ async fn run() {
 
         storage.new_bucket::<GlobalPixelMap>("pixelmap", global_pixel_map);
 
-        storage.new_bucket::<ConwayGrid>("optim_grid", optim_grid);
+        storage.insert_resource::<ConwayGrid>(optim_grid);
 
         let (width, height) = (cfg.window_width, cfg.window_height);
         storage.new_bucket::<(u32, u32)>("grid-size", (width, height));
@@ -0,0 +1,93 @@
+#![allow(unused_imports)]
+#![allow(dead_code)]
+
+// Reuse the Conway example's components, but step them without a window.
+#[path = "../conway/custom_components.rs"]
+pub mod custom_components;
+
+use std::time::Instant;
+
+use log::info;
+use pixpox_common::Camera;
+use pixpox_ecs::{HeadlessRunner, SyntheticInput, World};
+use pixpox_renderer::global_pixel_map::GlobalPixelMap;
+use winit::event::VirtualKeyCode;
+
+use crate::custom_components::ConwayGridComponent;
+
+const HEIGHT: u32 = 200;
+const WIDTH: u32 = 300;
+const TICKS: u64 = 500;
+
+fn main() {
+    env_logger::init();
+
+    let mut world = World::new();
+
+    let camera = Camera::new(0, 0, HEIGHT, WIDTH, HEIGHT, WIDTH);
+    let global_pixel_map = GlobalPixelMap::new_empty(HEIGHT, WIDTH, camera);
+    world
+        .storage
+        .write()
+        .unwrap()
+        .new_bucket::<GlobalPixelMap>("pixelmap", global_pixel_map);
+
+    let entity = world.spawn();
+    world.add_component_to_entity(entity, ConwayGridComponent::new(HEIGHT, WIDTH, 0.10));
+
+    let mut runner = HeadlessRunner::<GlobalPixelMap>::new(world);
+
+    // The grid starts out paused, press P to start it
+    runner.queue_input(SyntheticInput::new().press_key(VirtualKeyCode::P));
+
+    // Optionally write a trace and the metrics of the run, e.g.
+    // `cargo run --example headless -- --trace trace.json --metrics metrics.csv`
+    let (mut trace_path, mut metrics_path) = (None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace_path = args.next(),
+            "--metrics" => metrics_path = args.next(),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    if trace_path.is_some() {
+        runner.start_trace();
+    }
+
+    if metrics_path.is_some() {
+        runner.world().stats.write().unwrap().recorder_mut().start();
+    }
+
+    let now = Instant::now();
+    runner.run(TICKS);
+    let elapsed = now.elapsed();
+
+    if let Some(path) = trace_path {
+        runner.save_trace(&path).expect("Could not save trace");
+        info!("Saved trace to {}", path);
+    }
+
+    if let Some(path) = metrics_path {
+        let mut stats = runner.world().stats.write().unwrap();
+        stats.recorder_mut().stop();
+        stats.recorder().save(&path).expect("Could not save metrics");
+        info!("Saved metrics to {}", path);
+    }
+
+    let frame = runner.render().expect("Could not render Pixel Map");
+    let alive = frame
+        .chunks_exact(4)
+        .filter(|pixel| pixel[..3].iter().any(|&c| c > 0))
+        .count();
+
+    println!(
+        "{} ticks in {:.2?} ({:.0} ticks/s), {} of {} cells alive",
+        runner.ticks(),
+        elapsed,
+        runner.ticks() as f64 / elapsed.as_secs_f64(),
+        alive,
+        HEIGHT * WIDTH
+    );
+}
@@ -4,7 +4,7 @@ use log::{debug, error, info};
 use pixpox_app::App;
 use pixpox_ecs::{
     entity::{self, Entity},
-    Label, Run, Storage, Texture, Update, World, InputHandler,
+    Commands, Component, Mut, Run, Storage, Texture, Update, World, InputHandler,
 };
 use pixpox_utils::{
     conway::ConwayGrid,
@@ -18,7 +18,8 @@ use winit_input_helper::WinitInputHelper;
 
 use crate::GlobalPixelMap;
 
-#[derive(Clone)]
+#[derive(Clone, Component)]
+#[component(label = "CellRealm", run, update)]
 pub struct CellRealmComponent {
     inner: CellRealm,
     paused: bool,
@@ -34,67 +35,67 @@ impl CellRealmComponent {
 }
 
 impl Run for CellRealmComponent {
-    fn run(&mut self, _storage: &pixpox_ecs::Storage) {
-        if !self.paused {
-            self.inner.next_state();
+    fn run(mut this: Mut<Self>, _storage: &pixpox_ecs::Storage, _commands: &Commands) {
+        if !this.paused {
+            this.inner.next_state();
         }
     }
 }
 
 impl Update for CellRealmComponent {
-    fn update(&mut self, storage: &RwLock<pixpox_ecs::Storage>, input: &InputHandler, stats: &RwLock<Stats>) {
-        let mut storage = storage.write().unwrap();
+    fn update(
+        mut this: Mut<Self>,
+        storage: &RwLock<pixpox_ecs::Storage>,
+        input: &InputHandler,
+        stats: &RwLock<Stats>,
+        _commands: &Commands,
+    ) {
+        let storage = storage.read().unwrap();
 
         if input.winit.key_pressed(VirtualKeyCode::P) {
             info!("Toggled world");
-            self.paused = !self.paused;
+            this.paused = !this.paused;
         }
 
         // Left mouse click
         if input.winit.mouse_held(0) {
             info!("mouse pos: [{}, {}]", input.mouse.0, input.mouse.1);
-            self.inner.set_circle(input.mouse, 30, Cell::SAND);
+            this.inner.set_circle(input.mouse, 30, Cell::SAND);
         }
 
         // Right mouse click
         if input.winit.mouse_held(1) {
             info!("mouse pos: [{}, {}]", input.mouse.0, input.mouse.1);
-            self.inner.set_circle(input.mouse, 10, Cell::WATER);
+            this.inner.set_circle(input.mouse, 10, Cell::WATER);
         }
 
         // Middle mouse click
         if input.winit.mouse_held(2) {
             info!("mouse pos: [{}, {}]", input.mouse.0, input.mouse_prev.1);
 
-            self.inner.set_line(input.mouse, input.mouse_prev, Cell::SOLID);
+            this.inner.set_line(input.mouse, input.mouse_prev, Cell::SOLID);
         }
 
         // clear grid
         if input.winit.key_pressed(VirtualKeyCode::C) {
             log::info!("Clear grid");
-            self.inner.clear_grid();
+            this.inner.clear_grid();
         }
 
         // stats.write().expect("couldnt lock").update_sector("label".to_string(), 123.0);
 
-        let cell_count = self.inner.get_cell_count();
+        let cell_count = this.inner.get_cell_count();
 
         // iterate over cell_coutn and update stats
         for (label, count) in cell_count {
-            stats.write().expect("couldnt lock").update_sector(label.to_string(), count as f32);
+            stats.write().expect("couldnt lock").update_value(label.to_string(), count as f32);
         }
 
         // Fetch PixelMap
-        let pixelmap = storage
-            .query_storage_mut::<GlobalPixelMap>("pixelmap")
+        let mut pixelmap = storage
+            .write_storage::<GlobalPixelMap>("pixelmap")
             .expect("Could not query Pixel Map");
 
-        pixelmap.draw_flat_vec(&mut self.inner.get_color_vec());
-    }
-}
-
-impl Label for CellRealmComponent {
-    fn label(&mut self) -> &'static str {
-        "CellRealm"
+        pixelmap.draw_flat_vec(&mut this.inner.get_color_vec());
     }
 }
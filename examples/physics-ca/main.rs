@@ -22,7 +22,7 @@ use pixpox_common::Camera;
 use pixpox_ecs::entity::Entity;
 use pixpox_ecs::{world, InputHandler, Texture, World};
 use pixpox_ecs::{Run, Update};
-use pixpox_renderer::gui::{GuiChild, GuiParent};
+use pixpox_renderer::gui::{stats_table, GuiChild, GuiParent};
 use pixpox_utils::CA::cell_realm::CellRealm;
 use pixpox_utils::{conway::ConwayGrid, Stats};
 use rand::Rng;
@@ -78,16 +78,13 @@ async fn run() {
     let mut show_metrics_closure = |ui: &mut Ui, state: &mut bool, stats: &Stats| {
         ui.window(cfg.window_title.clone())
             .position([60.0, 60.0], imgui::Condition::Once)
-            .size([200.0, 200.0], imgui::Condition::FirstUseEver)
+            .size([400.0, 300.0], imgui::Condition::FirstUseEver)
             .collapsible(true)
             .movable(true)
             .scrollable(true)
             .resizable(true)
             .build(|| {
-                // Show formatted stats as a list, more readable.
-                for (index, s) in stats.get_formatted_stats().iter().enumerate() {
-                    ui.text(format!("{}. {}", index + 1, s));
-                }
+                stats_table(ui, stats);
             });
     };
 
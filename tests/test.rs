@@ -0,0 +1,119 @@
+#![allow(unused_imports)]
+#![allow(dead_code)]
+
+// Drive the Conway example's components without a window.
+#[path = "../examples/conway/custom_components.rs"]
+pub mod custom_components;
+
+use pixpox_common::Camera;
+use pixpox_ecs::{GlobalPixelMap as GlobalPixelMapTrait, HeadlessRunner, SyntheticInput, World};
+use pixpox_renderer::global_pixel_map::GlobalPixelMap;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::custom_components::ConwayGridComponent;
+
+const HEIGHT: u32 = 10;
+const WIDTH: u32 = 10;
+
+fn conway_runner() -> HeadlessRunner<GlobalPixelMap> {
+    let mut world = World::new();
+
+    let camera = Camera::new(0, 0, HEIGHT, WIDTH, HEIGHT, WIDTH);
+    world
+        .storage
+        .write()
+        .unwrap()
+        .new_bucket::<GlobalPixelMap>("pixelmap", GlobalPixelMap::new_empty(HEIGHT, WIDTH, camera));
+
+    let entity = world.spawn();
+    world.add_component_to_entity(entity, ConwayGridComponent::new(HEIGHT, WIDTH, 0.5));
+
+    HeadlessRunner::new(world)
+}
+
+// Clears the random grid and drags the mouse from `from` to `to`, which draws every cell on
+// the way but not `from` itself. The grid is still paused afterwards.
+fn draw_line(
+    runner: &mut HeadlessRunner<GlobalPixelMap>,
+    from: (isize, isize),
+    to: (isize, isize),
+) {
+    runner
+        .queue_input(
+            SyntheticInput::new()
+                .press_key(VirtualKeyCode::C)
+                .mouse_at(from),
+        )
+        .queue_input(
+            SyntheticInput::new()
+                .mouse_at(to)
+                .press_mouse(MouseButton::Left),
+        )
+        .queue_input(SyntheticInput::new().release_mouse(MouseButton::Left))
+        .run(3);
+}
+
+// The cells drawn in the rendered frame, as (x, y).
+fn alive_cells(frame: &[u8]) -> Vec<(u32, u32)> {
+    frame
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(_, pixel)| pixel[..3].iter().any(|&c| c > 0))
+        .map(|(index, _)| (index as u32 % WIDTH, index as u32 / WIDTH))
+        .collect()
+}
+
+fn alive_count(world: &World) -> f32 {
+    world
+        .stats
+        .read()
+        .unwrap()
+        .value("alive cells")
+        .expect("No alive cells value")
+}
+
+#[test]
+fn blinker_oscillates() {
+    let mut runner = conway_runner();
+    draw_line(&mut runner, (3, 5), (6, 5));
+
+    let horizontal = vec![(4, 5), (5, 5), (6, 5)];
+    let vertical = vec![(5, 4), (5, 5), (5, 6)];
+
+    // Paused, so it stays as drawn
+    runner.run(5);
+    assert_eq!(alive_cells(&runner.render().unwrap()), horizontal);
+
+    runner.queue_input(SyntheticInput::new().press_key(VirtualKeyCode::P));
+    runner.step();
+    assert_eq!(alive_cells(&runner.render().unwrap()), horizontal);
+
+    runner.step();
+    assert_eq!(alive_cells(&runner.render().unwrap()), vertical);
+
+    runner.step();
+    assert_eq!(alive_cells(&runner.render().unwrap()), horizontal);
+
+    // The storage handed back holds the last frame drawn
+    let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+    let storage = runner.into_storage();
+    storage
+        .global_pixel_map::<GlobalPixelMap>()
+        .unwrap()
+        .render(&mut frame);
+    assert_eq!(alive_cells(&frame), horizontal);
+}
+
+#[test]
+fn lonely_cells_die() {
+    let mut runner = conway_runner();
+    draw_line(&mut runner, (2, 2), (4, 2));
+    assert_eq!(alive_count(runner.world()), 2.0);
+
+    // The tick that unpauses the grid only handles input, the next one steps it
+    runner.queue_input(SyntheticInput::new().press_key(VirtualKeyCode::P));
+    let ticks = runner.run_until(10, |world| alive_count(world) == 0.0);
+
+    assert_eq!(ticks, Some(2));
+    assert!(alive_cells(&runner.render().unwrap()).is_empty());
+}